@@ -1,4 +1,5 @@
-use crate::automation::AutomationEvent;
+use crate::automation::{describe_issues, validate_events, AutomationEvent};
+use crate::params;
 use dotenvy::dotenv;
 use serde::{Deserialize, Serialize};
 
@@ -40,24 +41,18 @@ pub async fn ai_generate_automation(
         .map_err(|_| "OPENAI_API_KEY not set".to_string())?;
     let model = std::env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-4o-mini".to_string());
 
-    let system = r#"You generate automation events for a synth. Return only a JSON array of events.
-Each event: {"time_ms": number, "path": string, "value": number|string|boolean, "curve": "step"|"linear"}.
+    let system = format!(
+        r#"You generate automation events for a synth. Return only a JSON array of events.
+Each event: {{"time_ms": number (fractions allowed), "path": string, "value": number|string|boolean, "curve": curve}}.
+The curve shapes the move from this event to the next event on the same path:
+"step" (jump, the default), "linear", "exponential" (best for cutoff and pitch), "logarithmic",
+"s_curve" (ease in and out) or {{"bezier": {{"tension": number -1..1}}}}.
+Only "number" paths can use a curve other than "step".
 Valid paths:
-- oscillator.waveform (string: sine|triangle|saw|square)
-- oscillator.tune (number)
-- oscillator.level (number 0..1)
-- oscillator.sync (boolean)
-- filter.cutoff (number 20..20000)
-- filter.resonance (number 0..1)
-- filter.env_amount (number 0..1)
-- filter.drive (number 0..1)
-- mixer.noise (number 0..1)
-- mixer.sub (number 0..1)
-- mixer.master (number 0..1)
-- global.mono (boolean)
-- global.glide (number 0..1)
-- global.clip_amount (number 0.05..1)
-No extra text, no markdown, JSON only."#;
+{}
+No extra text, no markdown, JSON only."#,
+        params::prompt_reference()
+    );
 
     let user = format!(
         "Duration: {} ms. Prompt: {}",
@@ -69,7 +64,7 @@ No extra text, no markdown, JSON only."#;
         messages: vec![
             ChatMessage {
                 role: "system".to_string(),
-                content: system.to_string(),
+                content: system,
             },
             ChatMessage {
                 role: "user".to_string(),
@@ -103,12 +98,16 @@ No extra text, no markdown, JSON only."#;
         .map_err(|e| format!("openai response parse failed: {e}"))?;
     let content = parsed
         .choices
-        .get(0)
+        .first()
         .map(|c| c.message.content.clone())
         .unwrap_or_default();
 
     let events: Vec<AutomationEvent> = serde_json::from_str(&content)
         .map_err(|e| format!("automation json parse failed: {e}"))?;
+    let issues = validate_events(&events, request.duration_ms);
+    if !issues.is_empty() {
+        return Err(describe_issues(&issues));
+    }
 
     Ok(events)
 }
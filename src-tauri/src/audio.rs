@@ -1,186 +1,287 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Sample, SampleFormat, Stream};
-use std::f32::consts::PI;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig, I24, U24};
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use tauri::State;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+use triple_buffer::Output;
 
-use crate::synth::SynthEngine;
+use crate::automation::{describe_issues, validate_events, Automation, AutomationEvent, Player};
+use crate::devices::{load_options, open_output, save_options, AudioOptions, OutputDevice};
+use crate::dsp::Engine;
+use crate::synth::{SynthEngine, SynthState};
+
+/// Event carrying [`TransportPosition`] while automation plays live.
+const TRANSPORT_EVENT: &str = "automation://position";
+const TRANSPORT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Commands that can be queued before the audio thread drains them.
+const COMMAND_CAPACITY: usize = 256;
+
+/// Messages from command handlers to the engine on the audio thread.
+enum EngineCommand {
+    NoteOn { note: u8, velocity: f32 },
+    NoteOff { note: u8 },
+    /// `generation` is the transport generation the player was started in.
+    Play {
+        player: Box<Player>,
+        generation: u64,
+    },
+    Stop,
+}
+
+/// Live automation state shared between the audio thread, which advances
+/// it, and the thread reporting it to the UI.
+#[derive(Default)]
+struct Transport {
+    playing: AtomicBool,
+    /// Player position in samples.
+    position: AtomicU64,
+    /// Bumped on every play/stop so stale reporter threads exit.
+    generation: AtomicU64,
+}
+
+impl Transport {
+    fn halt(&self) {
+        self.playing.store(false, Ordering::Relaxed);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransportPosition {
+    pub position_ms: f64,
+    pub playing: bool,
+}
+
+/// Handler side of the rings shared with the audio thread.
+struct CommandQueue {
+    commands: Producer<EngineCommand>,
+    /// Players the audio thread is done with, returned to be freed here.
+    garbage: Consumer<Box<Player>>,
+}
+
+impl CommandQueue {
+    fn send(&mut self, command: EngineCommand) -> Result<(), String> {
+        while self.garbage.pop().is_ok() {}
+        self.commands
+            .push(command)
+            .map_err(|_| "audio command queue is full".to_string())
+    }
+}
+
+struct LiveStream {
+    /// Kept alive for as long as audio should play; dropping it stops the device.
+    _stream: Stream,
+    queue: CommandQueue,
+    sample_rate: u32,
+}
 
 pub struct AudioEngine {
-    stream: Mutex<Option<Stream>>,
+    stream: Mutex<Option<LiveStream>>,
+    transport: Arc<Transport>,
 }
 
 impl Default for AudioEngine {
     fn default() -> Self {
         Self {
             stream: Mutex::new(None),
+            transport: Arc::new(Transport::default()),
         }
     }
 }
 
-fn build_stream_with_state(state: Arc<Mutex<crate::synth::SynthState>>) -> Result<Stream, String> {
-    let host = cpal::default_host();
-    let device = host
-        .default_output_device()
-        .ok_or_else(|| "no default output device".to_string())?;
-    let config = device
-        .default_output_config()
-        .map_err(|e| format!("output config error: {e}"))?;
-    let stream_config: cpal::StreamConfig = config.clone().into();
+/// Everything the output callback owns. Nothing in here locks or
+/// allocates: state arrives through a triple buffer, commands through a
+/// ring, and finished players leave through another ring.
+struct StreamContext {
+    engine: Engine,
+    /// Latest synth state from the UI. Playing automation is written
+    /// straight into the read slot, which the audio thread owns until the
+    /// next update replaces it.
+    state: Output<SynthState>,
+    commands: Consumer<EngineCommand>,
+    garbage: Producer<Box<Player>>,
+    player: Option<Box<Player>>,
+    /// Transport generation `player` belongs to. A player left over from an
+    /// earlier generation must not report the transport as finished.
+    generation: u64,
+    transport: Arc<Transport>,
+}
+
+fn command_queue() -> (CommandQueue, Consumer<EngineCommand>, Producer<Box<Player>>) {
+    let (commands, receiver) = RingBuffer::new(COMMAND_CAPACITY);
+    let (returns, garbage) = RingBuffer::new(COMMAND_CAPACITY);
+    (CommandQueue { commands, garbage }, receiver, returns)
+}
+
+/// Frames rendered per engine call; keeps the mix buffer on the stack.
+const BLOCK_FRAMES: usize = 256;
+
+fn build_stream_with_state(
+    output: OutputDevice,
+    state: Output<SynthState>,
+    commands: Consumer<EngineCommand>,
+    garbage: Producer<Box<Player>>,
+    transport: Arc<Transport>,
+) -> Result<(Stream, u32), String> {
+    let OutputDevice {
+        device,
+        config: stream_config,
+        sample_format,
+    } = output;
     let sample_rate = stream_config.sample_rate as f32;
-    let channels = stream_config.channels as usize;
-    let err_fn = |err| eprintln!("audio stream error: {err}");
-
-    let stream = match config.sample_format() {
-        SampleFormat::F32 => {
-            let cfg = stream_config.clone();
-            let mut phase = 0.0f32;
-            let mut z = 0.0f32;
-            let state = state.clone();
-            device
-                .build_output_stream(
-                    &cfg,
-                    move |data: &mut [f32], _| {
-                        let (freq, waveform, level, master, cutoff, resonance, clip_amount) = state
-                            .lock()
-                            .map(|s| {
-                                let freq = 220.0 * 2.0f32.powf(s.oscillator.tune / 12.0);
-                                let waveform = s.oscillator.waveform.clone();
-                                let level = s.oscillator.level.max(0.0).min(1.0);
-                                let master = s.mixer.master.max(0.0).min(1.0);
-                                let cutoff = s.filter.cutoff.max(20.0).min(20000.0);
-                                let resonance = s.filter.resonance.max(0.0).min(1.0);
-                                let clip_amount = s.global.clip_amount.max(0.05).min(1.0);
-                                (freq, waveform, level, master, cutoff, resonance, clip_amount)
-                            })
-                            .unwrap_or((220.0, "sine".into(), 0.2, 0.7, 12000.0, 0.0, 0.35));
-                        let a = (-2.0 * PI * cutoff / sample_rate).exp();
-                        let feedback = (1.0 + resonance * 3.0).min(3.5);
-                        for frame in data.chunks_mut(channels) {
-                            phase = (phase + freq / sample_rate) % 1.0;
-                            let raw = wave_value(&waveform, phase) * level;
-                            let input = raw - z * (feedback - 1.0);
-                            z = (1.0 - a) * input + a * z;
-                            let value = soft_clip(z * master * clip_amount);
-                            for sample in frame.iter_mut() {
-                                *sample = value;
-                            }
-                        }
-                    },
-                    err_fn,
-                    None,
-                )
-                .map_err(|e| format!("stream build error: {e}"))?
-        }
-        SampleFormat::I16 => {
-            let cfg = stream_config.clone();
-            let mut phase = 0.0f32;
-            let mut z = 0.0f32;
-            let state = state.clone();
-            device
-                .build_output_stream(
-                    &cfg,
-                    move |data: &mut [i16], _| {
-                        let (freq, waveform, level, master, cutoff, resonance, clip_amount) = state
-                            .lock()
-                            .map(|s| {
-                                let freq = 220.0 * 2.0f32.powf(s.oscillator.tune / 12.0);
-                                let waveform = s.oscillator.waveform.clone();
-                                let level = s.oscillator.level.max(0.0).min(1.0);
-                                let master = s.mixer.master.max(0.0).min(1.0);
-                                let cutoff = s.filter.cutoff.max(20.0).min(20000.0);
-                                let resonance = s.filter.resonance.max(0.0).min(1.0);
-                                let clip_amount = s.global.clip_amount.max(0.05).min(1.0);
-                                (freq, waveform, level, master, cutoff, resonance, clip_amount)
-                            })
-                            .unwrap_or((220.0, "sine".into(), 0.2, 0.7, 12000.0, 0.0, 0.35));
-                        let a = (-2.0 * PI * cutoff / sample_rate).exp();
-                        let feedback = (1.0 + resonance * 3.0).min(3.5);
-                        for frame in data.chunks_mut(channels) {
-                            phase = (phase + freq / sample_rate) % 1.0;
-                            let raw = wave_value(&waveform, phase) * level;
-                            let input = raw - z * (feedback - 1.0);
-                            z = (1.0 - a) * input + a * z;
-                            let value = soft_clip(z * master * clip_amount);
-                            let sample = i16::from_sample(value);
-                            for out in frame.iter_mut() {
-                                *out = sample;
-                            }
-                        }
-                    },
-                    err_fn,
-                    None,
-                )
-                .map_err(|e| format!("stream build error: {e}"))?
+    let context = StreamContext::new(sample_rate, state, commands, garbage, transport);
+
+    let stream = match sample_format {
+        SampleFormat::I8 => build_stream::<i8>(&device, &stream_config, context),
+        SampleFormat::I16 => build_stream::<i16>(&device, &stream_config, context),
+        SampleFormat::I24 => build_stream::<I24>(&device, &stream_config, context),
+        SampleFormat::I32 => build_stream::<i32>(&device, &stream_config, context),
+        SampleFormat::I64 => build_stream::<i64>(&device, &stream_config, context),
+        SampleFormat::U8 => build_stream::<u8>(&device, &stream_config, context),
+        SampleFormat::U16 => build_stream::<u16>(&device, &stream_config, context),
+        SampleFormat::U24 => build_stream::<U24>(&device, &stream_config, context),
+        SampleFormat::U32 => build_stream::<u32>(&device, &stream_config, context),
+        SampleFormat::U64 => build_stream::<u64>(&device, &stream_config, context),
+        SampleFormat::F32 => build_stream::<f32>(&device, &stream_config, context),
+        SampleFormat::F64 => build_stream::<f64>(&device, &stream_config, context),
+        format => return Err(format!("unsupported sample format {format}")),
+    }?;
+
+    Ok((stream, stream_config.sample_rate))
+}
+
+fn build_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    mut context: StreamContext,
+) -> Result<Stream, String>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| context.fill(data, channels),
+            |err| eprintln!("audio stream error: {err}"),
+            None,
+        )
+        .map_err(|e| format!("stream build error: {e}"))
+}
+
+/// Left and right go to the first two channels and any others stay
+/// silent; a mono device gets the two folded together.
+fn write_frame<T>(frame: &mut [T], left: f32, right: f32)
+where
+    T: SizedSample + FromSample<f32>,
+{
+    match frame {
+        [mono] => *mono = T::from_sample((left + right) * 0.5),
+        [l, r, rest @ ..] => {
+            *l = T::from_sample(left);
+            *r = T::from_sample(right);
+            rest.fill(T::EQUILIBRIUM);
         }
-        SampleFormat::U16 => {
-            let cfg = stream_config.clone();
-            let mut phase = 0.0f32;
-            let mut z = 0.0f32;
-            let state = state.clone();
-            device
-                .build_output_stream(
-                    &cfg,
-                    move |data: &mut [u16], _| {
-                        let (freq, waveform, level, master, cutoff, resonance, clip_amount) = state
-                            .lock()
-                            .map(|s| {
-                                let freq = 220.0 * 2.0f32.powf(s.oscillator.tune / 12.0);
-                                let waveform = s.oscillator.waveform.clone();
-                                let level = s.oscillator.level.max(0.0).min(1.0);
-                                let master = s.mixer.master.max(0.0).min(1.0);
-                                let cutoff = s.filter.cutoff.max(20.0).min(20000.0);
-                                let resonance = s.filter.resonance.max(0.0).min(1.0);
-                                let clip_amount = s.global.clip_amount.max(0.05).min(1.0);
-                                (freq, waveform, level, master, cutoff, resonance, clip_amount)
-                            })
-                            .unwrap_or((220.0, "sine".into(), 0.2, 0.7, 12000.0, 0.0, 0.35));
-                        let a = (-2.0 * PI * cutoff / sample_rate).exp();
-                        let feedback = (1.0 + resonance * 3.0).min(3.5);
-                        for frame in data.chunks_mut(channels) {
-                            phase = (phase + freq / sample_rate) % 1.0;
-                            let raw = wave_value(&waveform, phase) * level;
-                            let input = raw - z * (feedback - 1.0);
-                            z = (1.0 - a) * input + a * z;
-                            let value = soft_clip(z * master * clip_amount);
-                            let sample = u16::from_sample(value);
-                            for out in frame.iter_mut() {
-                                *out = sample;
-                            }
-                        }
-                    },
-                    err_fn,
-                    None,
-                )
-                .map_err(|e| format!("stream build error: {e}"))?
+        [] => {}
+    }
+}
+
+impl StreamContext {
+    fn new(
+        sample_rate: f32,
+        mut state: Output<SynthState>,
+        commands: Consumer<EngineCommand>,
+        garbage: Producer<Box<Player>>,
+        transport: Arc<Transport>,
+    ) -> Self {
+        Self {
+            engine: Engine::new(sample_rate, state.read()),
+            state,
+            commands,
+            garbage,
+            player: None,
+            generation: 0,
+            transport,
         }
-        _ => return Err("unsupported sample format".to_string()),
-    };
+    }
 
-    Ok(stream)
-}
+    fn fill<T>(&mut self, data: &mut [T], channels: usize)
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        self.state.update();
+        while let Ok(command) = self.commands.pop() {
+            match command {
+                EngineCommand::NoteOn { note, velocity } => self.engine.note_on(note, velocity),
+                EngineCommand::NoteOff { note } => self.engine.note_off(note),
+                EngineCommand::Play { player, generation } => {
+                    self.generation = generation;
+                    self.retire(Some(player));
+                }
+                EngineCommand::Stop => self.retire(None),
+            }
+        }
 
-fn wave_value(waveform: &str, phase: f32) -> f32 {
-    match waveform {
-        "square" => {
-            if phase < 0.5 {
-                1.0
-            } else {
-                -1.0
+        let mut left = [0.0f32; BLOCK_FRAMES];
+        let mut right = [0.0f32; BLOCK_FRAMES];
+        let mut remaining = data.len() / channels;
+        let mut frames = data.chunks_mut(channels);
+        while remaining > 0 {
+            let state = self.state.output_buffer();
+            let mut len = remaining.min(BLOCK_FRAMES);
+            if let Some(player) = &self.player {
+                player.apply(state);
+                len = player.block_len(len as u64) as usize;
+            }
+            self.engine.set_state(state);
+            self.engine.process(&mut left[..len], &mut right[..len]);
+            for (frame, (l, r)) in frames.by_ref().take(len).zip(left.iter().zip(&right)) {
+                write_frame(frame, *l, *r);
+            }
+            let mut finished = false;
+            if let Some(player) = &mut self.player {
+                player.advance(len as u64);
+                self.transport
+                    .position
+                    .store(player.position(), Ordering::Relaxed);
+                finished = player.is_finished();
             }
+            // A finished one-shot player would keep writing its final values
+            // over every state update, so it goes as soon as it is done.
+            if finished {
+                self.retire(None);
+                if self.transport.generation.load(Ordering::Relaxed) == self.generation {
+                    self.transport.playing.store(false, Ordering::Relaxed);
+                }
+            }
+            remaining -= len;
         }
-        "saw" => 2.0 * phase - 1.0,
-        "triangle" => 1.0 - 4.0 * (phase - 0.5).abs(),
-        _ => (phase * 2.0 * PI).sin(),
     }
-}
 
-fn soft_clip(x: f32) -> f32 {
-    x / (1.0 + x.abs())
+    /// Swaps in `next` and hands the previous player back to be freed off
+    /// the audio thread.
+    fn retire(&mut self, next: Option<Box<Player>>) {
+        if let Some(previous) = std::mem::replace(&mut self.player, next) {
+            // The ring is as deep as the command ring and is drained before
+            // every send, so it only fills if the handlers stop draining.
+            let _ = self.garbage.push(previous);
+        }
+    }
 }
 
+/// Starts the output stream. Explicit `options` are saved for the next
+/// session; without them the saved options are used, falling back to the
+/// defaults if the saved device is gone.
 #[tauri::command]
-pub fn audio_start(state: State<AudioEngine>, synth: State<SynthEngine>) -> Result<bool, String> {
+pub fn audio_start(
+    options: Option<AudioOptions>,
+    state: State<AudioEngine>,
+    synth: State<SynthEngine>,
+) -> Result<bool, String> {
     let mut guard = state
         .stream
         .lock()
@@ -188,11 +289,35 @@ pub fn audio_start(state: State<AudioEngine>, synth: State<SynthEngine>) -> Resu
     if guard.is_some() {
         return Ok(false);
     }
-    let stream = build_stream_with_state(synth.state.clone())?;
+    let output = match (&options, load_options()) {
+        (Some(options), _) => open_output(options)?,
+        (None, Some(saved)) => open_output(&saved).or_else(|e| {
+            eprintln!("saved audio options unusable, using defaults: {e}");
+            open_output(&AudioOptions::default())
+        })?,
+        (None, None) => open_output(&AudioOptions::default())?,
+    };
+    let (queue, commands, garbage) = command_queue();
+    let (stream, sample_rate) = build_stream_with_state(
+        output,
+        synth.connect()?,
+        commands,
+        garbage,
+        state.transport.clone(),
+    )?;
     stream
         .play()
         .map_err(|e| format!("audio start failed: {e}"))?;
-    *guard = Some(stream);
+    *guard = Some(LiveStream {
+        _stream: stream,
+        queue,
+        sample_rate,
+    });
+    if let Some(options) = options {
+        if let Err(e) = save_options(&options) {
+            eprintln!("could not save audio options: {e}");
+        }
+    }
     Ok(true)
 }
 
@@ -203,6 +328,7 @@ pub fn audio_stop(state: State<AudioEngine>) -> Result<bool, String> {
         .lock()
         .map_err(|_| "audio state lock poisoned".to_string())?;
     let was_running = guard.take().is_some();
+    state.transport.halt();
     Ok(was_running)
 }
 
@@ -214,3 +340,312 @@ pub fn audio_is_running(state: State<AudioEngine>) -> Result<bool, String> {
         .map_err(|_| "audio state lock poisoned".to_string())?;
     Ok(guard.is_some())
 }
+
+fn send_command(state: &AudioEngine, command: EngineCommand) -> Result<(), String> {
+    let mut guard = state
+        .stream
+        .lock()
+        .map_err(|_| "audio state lock poisoned".to_string())?;
+    let live = guard.as_mut().ok_or("audio is not running")?;
+    live.queue.send(command)
+}
+
+#[tauri::command]
+pub fn note_on(note: u8, velocity: f32, state: State<AudioEngine>) -> Result<(), String> {
+    send_command(&state, EngineCommand::NoteOn { note, velocity })
+}
+
+#[tauri::command]
+pub fn note_off(note: u8, state: State<AudioEngine>) -> Result<(), String> {
+    send_command(&state, EngineCommand::NoteOff { note })
+}
+
+/// Plays `events` against the running stream, on top of the current synth
+/// state. Loops after `duration_ms` (default: the last event) when `loop` is
+/// set. Progress is emitted as `automation://position` events. A one-shot
+/// run falls back to the synth state when it ends.
+#[tauri::command]
+pub fn automation_play(
+    events: Vec<AutomationEvent>,
+    r#loop: bool,
+    duration_ms: Option<u64>,
+    app: AppHandle,
+    state: State<AudioEngine>,
+    synth: State<SynthEngine>,
+) -> Result<(), String> {
+    let issues = validate_events(&events, duration_ms.unwrap_or(u64::MAX));
+    if !issues.is_empty() {
+        return Err(describe_issues(&issues));
+    }
+    let mut guard = state
+        .stream
+        .lock()
+        .map_err(|_| "audio state lock poisoned".to_string())?;
+    let live = guard.as_mut().ok_or("audio is not running")?;
+
+    let automation = Automation::new(&events, live.sample_rate);
+    let loop_length = r#loop.then(|| {
+        duration_ms
+            .map(|ms| (ms as f64 * live.sample_rate as f64 / 1000.0) as u64)
+            .unwrap_or_else(|| automation.end())
+    });
+    // Drops values a previous run left on paths this one doesn't automate.
+    synth.republish()?;
+    let transport = state.transport.clone();
+    transport.halt();
+    transport.position.store(0, Ordering::Relaxed);
+    transport.playing.store(true, Ordering::Relaxed);
+    let player = Player::new(automation, loop_length);
+    let generation = transport.generation.load(Ordering::Relaxed);
+    live.queue.send(EngineCommand::Play {
+        player: Box::new(player),
+        generation,
+    })?;
+
+    let sample_rate = live.sample_rate as f64;
+    std::thread::spawn(move || {
+        while transport.generation.load(Ordering::Relaxed) == generation {
+            let playing = transport.playing.load(Ordering::Relaxed);
+            let position = transport.position.load(Ordering::Relaxed);
+            let _ = app.emit(
+                TRANSPORT_EVENT,
+                TransportPosition {
+                    position_ms: position as f64 * 1000.0 / sample_rate,
+                    playing,
+                },
+            );
+            if !playing {
+                // The run finished on its own; go back to the synth state.
+                if transport.generation.load(Ordering::Relaxed) == generation {
+                    let _ = app.state::<SynthEngine>().republish();
+                }
+                break;
+            }
+            std::thread::sleep(TRANSPORT_INTERVAL);
+        }
+    });
+    Ok(())
+}
+
+/// Stops live automation; parameters fall back to the synth state.
+#[tauri::command]
+pub fn automation_stop(state: State<AudioEngine>, synth: State<SynthEngine>) -> Result<(), String> {
+    state.transport.halt();
+    send_command(&state, EngineCommand::Stop)?;
+    // The stream's state slot still holds the automated values.
+    synth.republish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automation::{event, Curve};
+    use crate::synth::{prepared, state_buffer, SynthEngine};
+    use serde_json::json;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Counts allocations, reallocations and frees made by threads that opt
+    /// in, so tests running in parallel don't disturb each other.
+    struct CountingAllocator;
+
+    thread_local! {
+        static COUNTING: Cell<bool> = const { Cell::new(false) };
+        static COUNT: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn record() {
+        if COUNTING.with(Cell::get) {
+            COUNT.with(|count| count.set(count.get() + 1));
+        }
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            record();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            record();
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            record();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations(f: impl FnOnce()) -> usize {
+        COUNT.with(|count| count.set(0));
+        COUNTING.with(|counting| counting.set(true));
+        f();
+        COUNTING.with(|counting| counting.set(false));
+        COUNT.with(Cell::get)
+    }
+
+    #[test]
+    fn fill_does_not_allocate() {
+        const SAMPLE_RATE: u32 = 48_000;
+        let mut synth = SynthState::default();
+        let (mut input, output) = state_buffer(&synth);
+        let (mut queue, commands, garbage) = command_queue();
+        let mut context = StreamContext::new(
+            SAMPLE_RATE as f32,
+            output,
+            commands,
+            garbage,
+            Arc::default(),
+        );
+
+        let events = [
+            event(0.0, "filter.cutoff", json!(200.0), Curve::Exponential),
+            event(250.0, "filter.cutoff", json!(8000.0), Curve::Linear),
+            event(0.0, "oscillator.waveform", json!("sine"), Curve::Step),
+            event(100.0, "oscillator.waveform", json!("triangle"), Curve::Step),
+            event(200.0, "filter.mode", json!("bandpass"), Curve::Step),
+            event(300.0, "global.mono", json!(true), Curve::Step),
+        ];
+        let player = Player::new(Automation::new(&events, SAMPLE_RATE), Some(24_000));
+        queue
+            .send(EngineCommand::NoteOn {
+                note: 60,
+                velocity: 1.0,
+            })
+            .unwrap();
+        queue
+            .send(EngineCommand::Play {
+                player: Box::new(player),
+                generation: 0,
+            })
+            .unwrap();
+
+        // Two interleaved channels, as most devices ask for.
+        let mut data = vec![0.0f32; 2 * 512];
+        let pass = |context: &mut StreamContext, data: &mut [f32]| {
+            allocations(|| {
+                for _ in 0..50 {
+                    context.fill(data, 2);
+                }
+            })
+        };
+        assert_eq!(pass(&mut context, &mut data), 0);
+        assert!(
+            data.iter().any(|sample| *sample != 0.0),
+            "held note is silent"
+        );
+
+        // A state change from the UI mid-playback.
+        synth.filter.resonance = 0.8;
+        synth.oscillator.waveform = "square".into();
+        input.write(prepared(&synth));
+        queue.send(EngineCommand::NoteOff { note: 60 }).unwrap();
+        assert_eq!(pass(&mut context, &mut data), 0);
+
+        queue.send(EngineCommand::Stop).unwrap();
+        assert_eq!(pass(&mut context, &mut data), 0);
+        assert!(
+            queue.garbage.pop().is_ok(),
+            "stopped player was not returned"
+        );
+    }
+
+    #[test]
+    fn stale_player_does_not_stop_the_transport() {
+        const SAMPLE_RATE: u32 = 1000;
+        let (_input, output) = state_buffer(&SynthState::default());
+        let (mut queue, commands, garbage) = command_queue();
+        let transport = Arc::new(Transport::default());
+        let mut context = StreamContext::new(
+            SAMPLE_RATE as f32,
+            output,
+            commands,
+            garbage,
+            transport.clone(),
+        );
+        let one_shot = || {
+            let events = [event(0.0, "mixer.master", json!(0.5), Curve::Step)];
+            Box::new(Player::new(Automation::new(&events, SAMPLE_RATE), None))
+        };
+        let mut data = vec![0.0f32; 2 * 64];
+
+        transport.playing.store(true, Ordering::Relaxed);
+        queue
+            .send(EngineCommand::Play {
+                player: one_shot(),
+                generation: 0,
+            })
+            .unwrap();
+        context.fill(&mut data, 2);
+        assert!(!transport.playing.load(Ordering::Relaxed));
+
+        // `automation_play` restarts while the finished player is still
+        // installed; callbacks before the new player arrives must not undo it.
+        transport.halt();
+        transport.playing.store(true, Ordering::Relaxed);
+        context.fill(&mut data, 2);
+        assert!(transport.playing.load(Ordering::Relaxed));
+
+        let generation = transport.generation.load(Ordering::Relaxed);
+        queue
+            .send(EngineCommand::Play {
+                player: one_shot(),
+                generation,
+            })
+            .unwrap();
+        context.fill(&mut data, 2);
+        assert!(!transport.playing.load(Ordering::Relaxed));
+    }
+    #[test]
+    fn automated_values_give_way_to_the_synth_state() {
+        const SAMPLE_RATE: u32 = 1000;
+        let synth = SynthEngine::default();
+        let (mut queue, commands, garbage) = command_queue();
+        let mut context = StreamContext::new(
+            SAMPLE_RATE as f32,
+            synth.connect().unwrap(),
+            commands,
+            garbage,
+            Arc::default(),
+        );
+        let base = SynthState::default();
+        let play = |queue: &mut CommandQueue, events: &[AutomationEvent], loop_length| {
+            let player = Player::new(Automation::new(events, SAMPLE_RATE), loop_length);
+            queue
+                .send(EngineCommand::Play {
+                    player: Box::new(player),
+                    generation: 0,
+                })
+                .unwrap();
+        };
+        let master = [event(0.0, "mixer.master", json!(0.1), Curve::Step)];
+        let cutoff = [event(0.0, "filter.cutoff", json!(300.0), Curve::Step)];
+        let mut data = vec![0.0f32; 2 * 64];
+
+        // A finished one-shot player is retired, so UI updates stick again.
+        play(&mut queue, &master, None);
+        context.fill(&mut data, 2);
+        assert_eq!(context.state.output_buffer().mixer.master, 0.1);
+        assert!(context.player.is_none());
+        assert!(queue.garbage.pop().is_ok());
+        synth.republish().unwrap();
+        context.fill(&mut data, 2);
+        let state = context.state.output_buffer();
+        assert_eq!(state.mixer.master, base.mixer.master);
+
+        // A new run starts from the synth state, not from the last run's values.
+        play(&mut queue, &master, Some(500));
+        context.fill(&mut data, 2);
+        assert_eq!(context.state.output_buffer().mixer.master, 0.1);
+        synth.republish().unwrap();
+        play(&mut queue, &cutoff, Some(500));
+        context.fill(&mut data, 2);
+        let state = context.state.output_buffer();
+        assert_eq!(state.filter.cutoff, 300.0);
+        assert_eq!(state.mixer.master, base.mixer.master);
+    }
+}
@@ -1,49 +1,568 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
+use crate::params::{self, ParamKind, ValueError};
 use crate::synth::SynthState;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AutomationEvent {
-    pub time_ms: u64,
+    /// Fractional milliseconds are honored; each event lands on the nearest
+    /// sample at the render or device sample rate.
+    pub time_ms: f64,
     pub path: String,
     pub value: Value,
-    pub curve: Option<String>,
+    /// Shape of the segment from this event to the next one on the same path.
+    #[serde(default, deserialize_with = "deserialize_curve")]
+    pub curve: Curve,
+}
+
+/// Interpolation shapes for numeric automation. Non-numeric values always
+/// step. Unknown names fail to deserialize.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    #[default]
+    #[serde(alias = "hold")]
+    Step,
+    Linear,
+    /// Constant ratio per unit time between positive (or between negative)
+    /// values: even sweeps for cutoff and other frequency-like parameters.
+    Exponential,
+    /// Mirror image of `Exponential`: fast at first, settling into the target.
+    Logarithmic,
+    /// S-curve: eases out of the first value and into the second.
+    #[serde(rename = "s_curve", alias = "smoothstep")]
+    Smoothstep,
+    /// Quadratic bezier; `tension` in `-1.0..=1.0` bows the curve towards
+    /// the target value (positive) or the starting value (negative).
+    Bezier { tension: f32 },
+}
+
+/// Bend used by the warped fallbacks of the exponential/logarithmic curves.
+const CURVE_BEND: f64 = 9.0;
+
+impl Curve {
+    pub fn is_ramp(self) -> bool {
+        self != Curve::Step
+    }
+
+    /// Value `t` of the way (`0.0..=1.0`) from `from` to `to`.
+    pub fn interpolate(self, from: f64, to: f64, t: f64) -> f64 {
+        let lerp = |t: f64| from + (to - from) * t;
+        // Geometric interpolation only makes sense without a sign change.
+        let geometric = from * to > 0.0;
+        match self {
+            Curve::Step => from,
+            Curve::Linear => lerp(t),
+            Curve::Exponential if geometric => from * (to / from).powf(t),
+            Curve::Exponential => lerp(((1.0 + CURVE_BEND).powf(t) - 1.0) / CURVE_BEND),
+            Curve::Logarithmic if geometric => from + to - to * (from / to).powf(t),
+            Curve::Logarithmic => lerp((1.0 + CURVE_BEND * t).ln() / (1.0 + CURVE_BEND).ln()),
+            Curve::Smoothstep => lerp(t * t * (3.0 - 2.0 * t)),
+            Curve::Bezier { tension } => {
+                let control = (1.0 + tension.clamp(-1.0, 1.0) as f64) / 2.0;
+                lerp(2.0 * t * (1.0 - t) * control + t * t)
+            }
+        }
+    }
+}
+
+/// Treats an explicit `null` like a missing curve.
+fn deserialize_curve<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Curve, D::Error> {
+    Ok(Option::<Curve>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Events grouped by path with their sample positions resolved, so the value
+/// of every automated parameter can be evaluated at any sample.
+pub struct Automation {
+    lanes: Vec<Lane>,
+}
+
+struct Lane {
+    /// Sorted by sample; events at the same sample keep their input order.
+    points: Vec<Point>,
+    /// Only `Number` parameters ramp; integers, booleans and choices step
+    /// whatever their curve says.
+    ramps: bool,
+}
+
+struct Point {
+    sample: u64,
+    event: AutomationEvent,
+}
+
+impl Automation {
+    pub fn new(events: &[AutomationEvent], sample_rate: u32) -> Self {
+        let mut lanes: Vec<(String, Lane)> = Vec::new();
+        for event in events {
+            let point = Point {
+                sample: event_sample(event, sample_rate),
+                event: event.clone(),
+            };
+            match lanes.iter_mut().find(|(path, _)| *path == event.path) {
+                Some((_, lane)) => lane.points.push(point),
+                None => lanes.push((
+                    event.path.clone(),
+                    Lane {
+                        points: vec![point],
+                        ramps: is_continuous(&event.path),
+                    },
+                )),
+            }
+        }
+        let lanes = lanes
+            .into_iter()
+            .map(|(_, mut lane)| {
+                lane.points.sort_by_key(|p| p.sample);
+                lane
+            })
+            .collect();
+        Self { lanes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.is_empty()
+    }
+
+    /// Sample of the last event on any lane.
+    pub fn end(&self) -> u64 {
+        self.lanes
+            .iter()
+            .filter_map(|lane| lane.points.last().map(|p| p.sample))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Sets every automated parameter to its value at `position`. Paths
+    /// whose first event is still ahead keep their current value.
+    pub fn apply(&self, state: &mut SynthState, position: u64) {
+        for lane in &self.lanes {
+            let index = lane.points.partition_point(|p| p.sample <= position);
+            let Some(current) = index.checked_sub(1).map(|i| &lane.points[i]) else {
+                continue;
+            };
+            match lane.points.get(index) {
+                Some(next) if lane.ramps && current.event.curve.is_ramp() => {
+                    let t = (position - current.sample) as f64 / (next.sample - current.sample) as f64;
+                    match (current.event.value.as_f64(), next.event.value.as_f64()) {
+                        (Some(from), Some(to)) => {
+                            let value = Value::from(current.event.curve.interpolate(from, to, t));
+                            apply_value(state, &current.event.path, &value);
+                        }
+                        _ => apply_event(state, &current.event),
+                    }
+                }
+                _ => apply_event(state, &current.event),
+            }
+        }
+    }
+
+    /// First event strictly after `position`, on any lane.
+    pub fn next_event(&self, position: u64) -> Option<u64> {
+        self.lanes
+            .iter()
+            .filter_map(|lane| {
+                let index = lane.points.partition_point(|p| p.sample <= position);
+                lane.points.get(index).map(|p| p.sample)
+            })
+            .min()
+    }
+
+    /// Whether any lane is between a ramping event and the event after it,
+    /// so its value changes from sample to sample.
+    pub fn is_ramping(&self, position: u64) -> bool {
+        self.lanes.iter().any(|lane| {
+            let index = lane.points.partition_point(|p| p.sample <= position);
+            lane.ramps
+                && index > 0
+                && index < lane.points.len()
+                && lane.points[index - 1].event.curve.is_ramp()
+        })
+    }
+}
+
+/// How often ramping automation is re-evaluated; the engine's parameter
+/// smoothing fills in between.
+const CONTROL_BLOCK: u64 = 32;
+
+/// Steps an [`Automation`] along a sample clock. The renderer and the live
+/// stream drive it the same way, so automation lands on the same samples in
+/// both.
+pub struct Player {
+    automation: Automation,
+    position: u64,
+    /// Wraps back to the start after this many samples when set.
+    loop_length: Option<u64>,
+}
+
+impl Player {
+    pub fn new(automation: Automation, loop_length: Option<u64>) -> Self {
+        Self {
+            automation,
+            position: 0,
+            loop_length: loop_length.map(|length| length.max(1)),
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.automation.is_empty()
+    }
+
+    /// A one-shot player is finished once its last event has been applied;
+    /// it keeps holding the final values until it is dropped.
+    pub fn is_finished(&self) -> bool {
+        self.loop_length.is_none() && self.position > self.automation.end()
+    }
+
+    pub fn apply(&self, state: &mut SynthState) {
+        self.automation.apply(state, self.position);
+    }
+
+    /// How many samples, up to `max`, can be processed before the state has
+    /// to be applied again.
+    pub fn block_len(&self, max: u64) -> u64 {
+        let mut len = max;
+        if let Some(next) = self.automation.next_event(self.position) {
+            len = len.min(next - self.position);
+        }
+        if self.automation.is_ramping(self.position) {
+            len = len.min(CONTROL_BLOCK);
+        }
+        if let Some(length) = self.loop_length {
+            len = len.min(length - self.position);
+        }
+        len.max(1)
+    }
+
+    pub fn advance(&mut self, samples: u64) {
+        self.position += samples;
+        if let Some(length) = self.loop_length {
+            if self.position >= length {
+                self.position = 0;
+            }
+        }
+    }
+}
+
+/// Whether `path` is a `Number` parameter, the only kind a curve applies to.
+fn is_continuous(path: &str) -> bool {
+    params::find(path).is_some_and(|p| matches!(p.kind, ParamKind::Number { .. }))
+}
+
+/// Sample nearest to the event time.
+fn event_sample(event: &AutomationEvent, sample_rate: u32) -> u64 {
+    (event.time_ms.max(0.0) * sample_rate as f64 / 1000.0).round() as u64
 }
 
 pub fn apply_event(state: &mut SynthState, event: &AutomationEvent) {
-    let path = event.path.as_str();
-    match path {
-        "oscillator.waveform" => {
-            if let Some(value) = event.value.as_str() {
-                state.oscillator.waveform = value.to_string();
+    apply_value(state, &event.path, &event.value);
+}
+
+/// Events are validated before they are played, so anything that still
+/// fails to apply here is skipped rather than aborting playback.
+fn apply_value(state: &mut SynthState, path: &str, value: &Value) {
+    let _ = state.set_path(path, value);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    UnknownPath,
+    NotAutomatable,
+    TypeMismatch,
+    OutOfRange,
+    /// A ramping curve on a parameter that can only step.
+    UnsupportedCurve,
+    InvalidTime,
+    BeyondDuration,
+    DuplicateTime,
+}
+
+/// A problem with one event, reported by [`validate_events`].
+#[derive(Debug, Clone, Serialize)]
+pub struct EventIssue {
+    /// Position of the event in the list that was validated.
+    pub index: usize,
+    pub path: String,
+    pub kind: IssueKind,
+    pub message: String,
+}
+
+/// Checks every event against the known parameter paths and the render
+/// length. Returns one entry per problem; an empty list means the events
+/// will be applied exactly as written.
+pub fn validate_events(events: &[AutomationEvent], duration_ms: u64) -> Vec<EventIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |index: usize, kind: IssueKind, message: String| {
+        issues.push(EventIssue {
+            index,
+            path: events[index].path.clone(),
+            kind,
+            message,
+        });
+    };
+
+    for (index, event) in events.iter().enumerate() {
+        if !event.time_ms.is_finite() || event.time_ms < 0.0 {
+            issue(
+                index,
+                IssueKind::InvalidTime,
+                format!("time {} ms is not a non-negative number", event.time_ms),
+            );
+        } else if event.time_ms > duration_ms as f64 {
+            issue(
+                index,
+                IssueKind::BeyondDuration,
+                format!("time {} ms is past the {duration_ms} ms duration", event.time_ms),
+            );
+        }
+        if events[..index]
+            .iter()
+            .any(|e| e.path == event.path && e.time_ms == event.time_ms)
+        {
+            issue(
+                index,
+                IssueKind::DuplicateTime,
+                format!("another {} event is already at {} ms", event.path, event.time_ms),
+            );
+        }
+
+        let Some(parameter) = params::find(&event.path) else {
+            issue(
+                index,
+                IssueKind::UnknownPath,
+                format!("unknown parameter path {:?}", event.path),
+            );
+            continue;
+        };
+        if !parameter.automatable {
+            issue(
+                index,
+                IssueKind::NotAutomatable,
+                format!("{} cannot be automated", event.path),
+            );
+            continue;
+        }
+        match parameter.check(&event.value) {
+            Err(ValueError::TypeMismatch(message)) => {
+                issue(index, IssueKind::TypeMismatch, message)
             }
+            Err(ValueError::OutOfRange(message)) => issue(index, IssueKind::OutOfRange, message),
+            Ok(()) => {}
+        }
+        if event.curve.is_ramp() && !matches!(parameter.kind, ParamKind::Number { .. }) {
+            issue(
+                index,
+                IssueKind::UnsupportedCurve,
+                format!("{} only steps; use the \"step\" curve", event.path),
+            );
         }
-        "oscillator.tune" => apply_f32(&event.value, &mut state.oscillator.tune),
-        "oscillator.level" => apply_f32(&event.value, &mut state.oscillator.level),
-        "oscillator.sync" => apply_bool(&event.value, &mut state.oscillator.sync),
-        "filter.cutoff" => apply_f32(&event.value, &mut state.filter.cutoff),
-        "filter.resonance" => apply_f32(&event.value, &mut state.filter.resonance),
-        "filter.env_amount" => apply_f32(&event.value, &mut state.filter.env_amount),
-        "filter.drive" => apply_f32(&event.value, &mut state.filter.drive),
-        "mixer.noise" => apply_f32(&event.value, &mut state.mixer.noise),
-        "mixer.sub" => apply_f32(&event.value, &mut state.mixer.sub),
-        "mixer.master" => apply_f32(&event.value, &mut state.mixer.master),
-        "global.mono" => apply_bool(&event.value, &mut state.global.mono),
-        "global.glide" => apply_f32(&event.value, &mut state.global.glide),
-        "global.clip_amount" => apply_f32(&event.value, &mut state.global.clip_amount),
-        _ => {}
     }
+    issues
+}
+
+/// Folds a validation report into the single error string commands return.
+pub fn describe_issues(issues: &[EventIssue]) -> String {
+    let details: Vec<String> = issues
+        .iter()
+        .map(|i| format!("event {} ({}): {}", i.index, i.path, i.message))
+        .collect();
+    format!("invalid automation: {}", details.join("; "))
 }
 
-fn apply_f32(value: &Value, target: &mut f32) {
-    if let Some(v) = value.as_f64() {
-        *target = v as f32;
+#[tauri::command]
+pub fn automation_validate(events: Vec<AutomationEvent>, duration_ms: u64) -> Vec<EventIssue> {
+    validate_events(&events, duration_ms)
+}
+
+/// Shorthand for building events in tests.
+#[cfg(test)]
+pub(crate) fn event(time_ms: f64, path: &str, value: Value, curve: Curve) -> AutomationEvent {
+    AutomationEvent {
+        time_ms,
+        path: path.into(),
+        value,
+        curve,
     }
 }
 
-fn apply_bool(value: &Value, target: &mut bool) {
-    if let Some(v) = value.as_bool() {
-        *target = v;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn integer_parameters_step_through_ramps() {
+        let events = [
+            event(0.0, "filter.slope", json!(12), Curve::Linear),
+            event(100.0, "filter.slope", json!(24), Curve::Step),
+        ];
+        let automation = Automation::new(&events, 1000);
+        let mut state = SynthState::default();
+        state.filter.slope = 24;
+        automation.apply(&mut state, 50);
+        assert_eq!(state.filter.slope, 12);
+        assert!(!automation.is_ramping(50));
+        automation.apply(&mut state, 100);
+        assert_eq!(state.filter.slope, 24);
+
+        let issues = validate_events(&events, 1000);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].index, 0);
+        assert_eq!(issues[0].kind, IssueKind::UnsupportedCurve);
+    }
+
+    fn kinds(events: &[AutomationEvent], duration_ms: u64) -> Vec<(usize, IssueKind)> {
+        validate_events(events, duration_ms)
+            .into_iter()
+            .map(|issue| (issue.index, issue.kind))
+            .collect()
+    }
+
+    #[test]
+    fn validation_reports_each_issue() {
+        let step = |time_ms, path, value| event(time_ms, path, value, Curve::Step);
+        assert!(kinds(&[step(0.0, "filter.cutoff", json!(800))], 1000).is_empty());
+        assert_eq!(
+            kinds(&[step(0.0, "filter.nope", json!(1))], 1000),
+            [(0, IssueKind::UnknownPath)]
+        );
+        assert_eq!(
+            kinds(&[step(0.0, "global.polyphony", json!(4))], 1000),
+            [(0, IssueKind::NotAutomatable)]
+        );
+        assert_eq!(
+            kinds(&[step(0.0, "filter.cutoff", json!("high"))], 1000),
+            [(0, IssueKind::TypeMismatch)]
+        );
+        assert_eq!(
+            kinds(&[step(0.0, "filter.cutoff", json!(5))], 1000),
+            [(0, IssueKind::OutOfRange)]
+        );
+        assert_eq!(
+            kinds(&[step(0.0, "filter.slope", json!(18))], 1000),
+            [(0, IssueKind::OutOfRange)]
+        );
+        assert_eq!(
+            kinds(&[step(-1.0, "filter.cutoff", json!(800))], 1000),
+            [(0, IssueKind::InvalidTime)]
+        );
+        assert_eq!(
+            kinds(&[step(f64::NAN, "filter.cutoff", json!(800))], 1000),
+            [(0, IssueKind::InvalidTime)]
+        );
+        assert_eq!(
+            kinds(&[step(1000.5, "filter.cutoff", json!(800))], 1000),
+            [(0, IssueKind::BeyondDuration)]
+        );
+        assert_eq!(
+            kinds(
+                &[
+                    step(10.0, "filter.cutoff", json!(800)),
+                    step(10.0, "filter.resonance", json!(0.5)),
+                    step(10.0, "filter.cutoff", json!(900)),
+                ],
+                1000
+            ),
+            [(2, IssueKind::DuplicateTime)]
+        );
+        assert_eq!(
+            kinds(&[event(0.0, "global.mono", json!(true), Curve::Linear)], 1000),
+            [(0, IssueKind::UnsupportedCurve)]
+        );
+    }
+
+    #[test]
+    fn curves_hit_both_endpoints() {
+        let curves = [
+            Curve::Step,
+            Curve::Linear,
+            Curve::Exponential,
+            Curve::Logarithmic,
+            Curve::Smoothstep,
+            Curve::Bezier { tension: 0.8 },
+            Curve::Bezier { tension: -0.8 },
+        ];
+        for (from, to) in [(100.0, 8000.0), (-1.0, 1.0), (0.0, 0.5)] {
+            for curve in curves {
+                let start = curve.interpolate(from, to, 0.0);
+                assert!((start - from).abs() < 1e-9, "{curve:?} starts at {start}");
+                if curve.is_ramp() {
+                    let end = curve.interpolate(from, to, 1.0);
+                    assert!((end - to).abs() < 1e-9, "{curve:?} ends at {end}");
+                    let middle = curve.interpolate(from, to, 0.5);
+                    assert!(middle > from.min(to) && middle < from.max(to));
+                }
+            }
+        }
+        assert_eq!(Curve::Step.interpolate(1.0, 2.0, 0.99), 1.0);
+        // Geometric: halfway between 100 and 10000 is 1000.
+        assert!((Curve::Exponential.interpolate(100.0, 10000.0, 0.5) - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn player_blocks_stop_at_events_and_ramps() {
+        let events = [
+            event(0.0, "filter.cutoff", json!(500), Curve::Step),
+            event(100.0, "filter.cutoff", json!(1000), Curve::Linear),
+            event(200.0, "filter.cutoff", json!(2000), Curve::Step),
+        ];
+        let mut player = Player::new(Automation::new(&events, 1000), None);
+        assert_eq!(player.block_len(1024), 100);
+        player.advance(100);
+        assert_eq!(player.block_len(1024), CONTROL_BLOCK);
+        assert_eq!(player.block_len(5), 5);
+
+        let mut state = SynthState::default();
+        player.advance(50);
+        player.apply(&mut state);
+        assert!((state.filter.cutoff - 1500.0).abs() < 1e-3);
+
+        player.advance(50);
+        assert_eq!(player.block_len(1024), 1024);
+        assert!(!player.is_finished());
+        player.advance(1);
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn fractional_times_land_on_the_nearest_sample() {
+        // 10.02 ms is 441.882 samples at 44.1 kHz: sample 442, not 441.
+        let events = [
+            event(0.0, "mixer.master", json!(0.2), Curve::Step),
+            event(10.02, "mixer.master", json!(0.8), Curve::Step),
+        ];
+        let mut player = Player::new(Automation::new(&events, 44_100), None);
+        assert_eq!(player.block_len(1024), 442);
+
+        let mut state = SynthState::default();
+        player.advance(441);
+        player.apply(&mut state);
+        assert_eq!(state.mixer.master, 0.2);
+        player.advance(1);
+        player.apply(&mut state);
+        assert_eq!(state.mixer.master, 0.8);
+    }
+
+    #[test]
+    fn looping_player_wraps_to_the_start() {
+        let events = [
+            event(0.0, "mixer.master", json!(0.2), Curve::Step),
+            event(30.0, "mixer.master", json!(0.8), Curve::Step),
+        ];
+        let mut player = Player::new(Automation::new(&events, 1000), Some(50));
+        player.advance(30);
+        assert_eq!(player.block_len(1024), 20);
+        player.advance(20);
+        assert_eq!(player.position(), 0);
+        assert!(!player.is_finished());
+
+        let mut state = SynthState::default();
+        player.apply(&mut state);
+        assert_eq!(state.mixer.master, 0.2);
     }
 }
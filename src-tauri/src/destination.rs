@@ -0,0 +1,230 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Subdirectory used under the desktop and music directories.
+const SAMPLES_DIR: &str = "Andromeda Samples";
+pub const DEFAULT_TEMPLATE: &str = "andromeda-render-{timestamp}";
+/// Highest suffix tried by [`Overwrite::Unique`] before giving up.
+const MAX_UNIQUE_SUFFIX: u32 = 9999;
+
+/// What to do when the rendered file name is already taken.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Overwrite {
+    /// Append `-2`, `-3`, ... until the name is free.
+    #[default]
+    Unique,
+    Replace,
+    /// Refuse to render.
+    Fail,
+}
+
+/// Values for the `{token}`s in a filename template.
+pub struct NameFields<'a> {
+    pub preset: &'a str,
+    pub sample_rate: u32,
+    pub note: u8,
+    pub duration_ms: u64,
+    pub channels: u16,
+}
+
+const TOKENS: &[&str] = &[
+    "preset",
+    "date",
+    "time",
+    "timestamp",
+    "sample_rate",
+    "note",
+    "duration_ms",
+    "channels",
+];
+
+/// `output_dir` if given (a leading `~` is the home directory), otherwise
+/// the first of desktop, music or data directory that exists on this system.
+pub fn output_dir(requested: Option<&str>) -> Result<PathBuf, String> {
+    let dir = match requested {
+        Some(dir) => match dir.strip_prefix('~') {
+            Some(rest) => {
+                let home = dirs::home_dir().ok_or("home directory not found")?;
+                home.join(rest.trim_start_matches(['/', '\\']))
+            }
+            None => PathBuf::from(dir),
+        },
+        None => dirs::desktop_dir()
+            .map(|dir| dir.join(SAMPLES_DIR))
+            .or_else(|| dirs::audio_dir().map(|dir| dir.join(SAMPLES_DIR)))
+            .or_else(|| dirs::data_dir().map(|dir| dir.join("andromeda").join("renders")))
+            .ok_or("no desktop, music or data directory found; set output_dir")?,
+    };
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("failed to create output directory {}: {e}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Expands `template` into a file stem. Characters that aren't safe in file
+/// names on every platform become `_`.
+pub fn file_stem(template: &str, fields: &NameFields) -> Result<String, String> {
+    let now = Local::now();
+    let mut stem = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        stem.push_str(&rest[..open]);
+        let close = rest[open..]
+            .find('}')
+            .map(|i| open + i)
+            .ok_or_else(|| format!("unclosed '{{' in filename template {template:?}"))?;
+        let value = match &rest[open + 1..close] {
+            "preset" => fields.preset.to_string(),
+            "date" => now.format("%Y-%m-%d").to_string(),
+            "time" => now.format("%H-%M-%S").to_string(),
+            "timestamp" => now.timestamp_millis().to_string(),
+            "sample_rate" => fields.sample_rate.to_string(),
+            "note" => fields.note.to_string(),
+            "duration_ms" => fields.duration_ms.to_string(),
+            "channels" => fields.channels.to_string(),
+            token => {
+                return Err(format!(
+                    "unknown filename token {{{token}}}; expected one of {}",
+                    TOKENS.join(", ")
+                ))
+            }
+        };
+        stem.push_str(&value);
+        rest = &rest[close + 1..];
+    }
+    stem.push_str(rest);
+
+    let stem: String = stem
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let stem = stem.trim().trim_matches('.');
+    if stem.is_empty() {
+        return Err(format!("filename template {template:?} produced an empty name"));
+    }
+    Ok(stem.to_string())
+}
+
+/// Creates `dir/stem.extension` according to `policy`, returning the open
+/// file and its final path. Unique names are claimed atomically, so two
+/// renders started together can't pick the same one.
+pub fn create(
+    dir: &Path,
+    stem: &str,
+    extension: &str,
+    policy: Overwrite,
+) -> Result<(File, PathBuf), String> {
+    let path = dir.join(format!("{stem}.{extension}"));
+    let open_new = |path: &Path| File::options().write(true).create_new(true).open(path);
+    match policy {
+        Overwrite::Replace => File::create(&path)
+            .map(|file| (file, path.clone()))
+            .map_err(|e| format!("failed to create {}: {e}", path.display())),
+        Overwrite::Fail => match open_new(&path) {
+            Ok(file) => Ok((file, path)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                Err(format!("{} already exists", path.display()))
+            }
+            Err(e) => Err(format!("failed to create {}: {e}", path.display())),
+        },
+        Overwrite::Unique => {
+            let mut candidate = path;
+            for suffix in 2..=MAX_UNIQUE_SUFFIX + 1 {
+                match open_new(&candidate) {
+                    Ok(file) => return Ok((file, candidate)),
+                    Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                        candidate = dir.join(format!("{stem}-{suffix}.{extension}"));
+                    }
+                    Err(e) => return Err(format!("failed to create {}: {e}", candidate.display())),
+                }
+            }
+            Err(format!("no free file name for {stem:?} in {}", dir.display()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn fields(preset: &str) -> NameFields<'_> {
+        NameFields {
+            preset,
+            sample_rate: 44_100,
+            note: 57,
+            duration_ms: 2000,
+            channels: 2,
+        }
+    }
+
+    /// A fresh directory under the system temp dir, removed by the caller.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("andromeda-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn expands_tokens() {
+        let stem = file_stem(
+            "{preset}_{note}_{sample_rate}_{duration_ms}_{channels}ch",
+            &fields("Pad"),
+        );
+        assert_eq!(stem.unwrap(), "Pad_57_44100_2000_2ch");
+
+        let dated = file_stem("{date} {time}", &fields("Pad")).unwrap();
+        assert_eq!(dated.len(), "2024-01-31 12-00-00".len());
+        assert!(file_stem("{timestamp}", &fields("Pad"))
+            .unwrap()
+            .chars()
+            .all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let unknown = file_stem("{preset}-{nope}", &fields("Pad")).unwrap_err();
+        assert!(unknown.contains("{nope}"), "{unknown}");
+        let unclosed = file_stem("render-{preset", &fields("Pad")).unwrap_err();
+        assert!(unclosed.contains("unclosed"), "{unclosed}");
+        assert!(file_stem("", &fields("Pad")).is_err());
+        assert!(file_stem(" {preset}. ", &fields("..")).is_err());
+    }
+
+    #[test]
+    fn sanitizes_unsafe_characters() {
+        let stem = file_stem("{preset}", &fields("a/b:c*d?e\\f")).unwrap();
+        assert_eq!(stem, "a_b_c_d_e_f");
+        assert_eq!(file_stem("x\ty<z>", &fields("")).unwrap(), "x_y_z_");
+    }
+
+    #[test]
+    fn overwrite_policies() {
+        let dir = scratch_dir("overwrite");
+        let (mut file, first) = create(&dir, "take", "wav", Overwrite::Unique).unwrap();
+        assert_eq!(first, dir.join("take.wav"));
+        file.write_all(b"first").unwrap();
+        let (_, second) = create(&dir, "take", "wav", Overwrite::Unique).unwrap();
+        assert_eq!(second, dir.join("take-2.wav"));
+        let (_, third) = create(&dir, "take", "wav", Overwrite::Unique).unwrap();
+        assert_eq!(third, dir.join("take-3.wav"));
+
+        let error = create(&dir, "take", "wav", Overwrite::Fail).unwrap_err();
+        assert!(error.contains("already exists"), "{error}");
+        assert_eq!(std::fs::read(&first).unwrap(), b"first");
+
+        let (_, replaced) = create(&dir, "take", "wav", Overwrite::Replace).unwrap();
+        assert_eq!(replaced, first);
+        assert!(std::fs::read(&first).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
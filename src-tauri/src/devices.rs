@@ -0,0 +1,211 @@
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{
+    BufferSize, Device, DeviceId, Host, HostId, SampleFormat, StreamConfig, SupportedBufferSize,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Output choices for `audio_start`. Anything left out uses the host's or
+/// device's default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioOptions {
+    /// Host name as listed by `audio_list_hosts`, e.g. `"alsa"` or `"jack"`.
+    pub host: Option<String>,
+    /// Device id as listed by `audio_list_devices`.
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    /// Frames per callback.
+    pub buffer_size: Option<u32>,
+    pub channels: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostInfo {
+    pub id: String,
+    pub name: &'static str,
+    pub is_default: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    pub id: String,
+    pub name: String,
+    pub is_default: bool,
+    /// Channel counts the device accepts, ascending.
+    pub channels: Vec<u16>,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub default_sample_rate: Option<u32>,
+    pub default_channels: Option<u16>,
+    /// Buffer size range in frames, when the host reports one.
+    pub min_buffer_size: Option<u32>,
+    pub max_buffer_size: Option<u32>,
+}
+
+/// A device and the stream settings chosen for it.
+pub struct OutputDevice {
+    pub device: Device,
+    pub config: StreamConfig,
+    pub sample_format: SampleFormat,
+}
+
+fn host(name: Option<&str>) -> Result<Host, String> {
+    match name {
+        None => Ok(cpal::default_host()),
+        Some(name) => {
+            let id = HostId::from_str(name).map_err(|_| format!("unknown audio host {name:?}"))?;
+            cpal::host_from_id(id).map_err(|e| format!("audio host {name:?} unavailable: {e}"))
+        }
+    }
+}
+
+fn device_id(device: &Device) -> Option<String> {
+    device.id().ok().map(|id| id.to_string())
+}
+
+fn device(host: &Host, id: Option<&str>) -> Result<Device, String> {
+    match id {
+        None => host
+            .default_output_device()
+            .ok_or_else(|| "no default output device".to_string()),
+        Some(id) => DeviceId::from_str(id)
+            .ok()
+            .and_then(|parsed| host.device_by_id(&parsed))
+            .ok_or_else(|| format!("output device {id:?} not found")),
+    }
+}
+
+/// Opens the device `options` describe and picks a stream config for it.
+pub fn open_output(options: &AudioOptions) -> Result<OutputDevice, String> {
+    let host = host(options.host.as_deref())?;
+    let device = device(&host, options.device.as_deref())?;
+    let default = device
+        .default_output_config()
+        .map_err(|e| format!("output config error: {e}"))?;
+
+    let supported = if options.sample_rate.is_none() && options.channels.is_none() {
+        default
+    } else {
+        let sample_rate = options.sample_rate.unwrap_or(default.sample_rate());
+        let channels = options.channels.unwrap_or(default.channels());
+        let mut candidates: Vec<_> = device
+            .supported_output_configs()
+            .map_err(|e| format!("output config error: {e}"))?
+            .filter(|range| {
+                range.channels() == channels
+                    && (range.min_sample_rate()..=range.max_sample_rate()).contains(&sample_rate)
+            })
+            .collect();
+        // Prefer the device's own format, then cpal's usual ranking.
+        candidates.sort_by(|a, b| {
+            (b.sample_format() == default.sample_format())
+                .cmp(&(a.sample_format() == default.sample_format()))
+                .then_with(|| b.cmp_default_heuristics(a))
+        });
+        candidates
+            .into_iter()
+            .next()
+            .map(|range| range.with_sample_rate(sample_rate))
+            .ok_or_else(|| {
+                format!("device does not support {channels} channel(s) at {sample_rate} Hz")
+            })?
+    };
+
+    let mut config = supported.config();
+    if let Some(frames) = options.buffer_size {
+        if let SupportedBufferSize::Range { min, max } = *supported.buffer_size() {
+            if !(min..=max).contains(&frames) {
+                return Err(format!(
+                    "buffer size {frames} is outside {min}..{max} frames"
+                ));
+            }
+        }
+        config.buffer_size = BufferSize::Fixed(frames);
+    }
+    Ok(OutputDevice {
+        device,
+        config,
+        sample_format: supported.sample_format(),
+    })
+}
+
+fn settings_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("andromeda").join("audio.json"))
+}
+
+/// Options saved by the last successful `audio_start`, if any.
+pub fn load_options() -> Option<AudioOptions> {
+    let text = std::fs::read_to_string(settings_path()?).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+pub fn save_options(options: &AudioOptions) -> Result<(), String> {
+    let path = settings_path().ok_or("config directory not found")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("create dir error: {e}"))?;
+    }
+    let text =
+        serde_json::to_string_pretty(options).map_err(|e| format!("serialize error: {e}"))?;
+    std::fs::write(&path, text).map_err(|e| format!("write error: {e}"))
+}
+
+#[tauri::command]
+pub fn audio_list_hosts() -> Vec<HostInfo> {
+    let default = cpal::default_host().id();
+    cpal::available_hosts()
+        .into_iter()
+        .map(|id| HostInfo {
+            id: id.to_string(),
+            name: id.name(),
+            is_default: id == default,
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn audio_list_devices(host: Option<String>) -> Result<Vec<DeviceInfo>, String> {
+    let host = self::host(host.as_deref())?;
+    let default_id = host.default_output_device().as_ref().and_then(device_id);
+    let devices = host
+        .output_devices()
+        .map_err(|e| format!("device list error: {e}"))?;
+
+    Ok(devices
+        .filter_map(|device| {
+            let id = device_id(&device)?;
+            let name = device
+                .description()
+                .map(|d| d.name().to_string())
+                .unwrap_or_else(|_| id.clone());
+            let ranges: Vec<_> = device.supported_output_configs().ok()?.collect();
+            let default = device.default_output_config().ok();
+            let mut channels: Vec<u16> = ranges.iter().map(|r| r.channels()).collect();
+            channels.sort_unstable();
+            channels.dedup();
+            let buffer = ranges.iter().find_map(|r| match *r.buffer_size() {
+                SupportedBufferSize::Range { min, max } => Some((min, max)),
+                SupportedBufferSize::Unknown => None,
+            });
+            Some(DeviceInfo {
+                is_default: default_id.as_ref() == Some(&id),
+                id,
+                name,
+                channels,
+                min_sample_rate: ranges.iter().map(|r| r.min_sample_rate()).min()?,
+                max_sample_rate: ranges.iter().map(|r| r.max_sample_rate()).max()?,
+                default_sample_rate: default.as_ref().map(|c| c.sample_rate()),
+                default_channels: default.as_ref().map(|c| c.channels()),
+                min_buffer_size: buffer.map(|(min, _)| min),
+                max_buffer_size: buffer.map(|(_, max)| max),
+            })
+        })
+        .collect())
+}
+
+/// The options `audio_start` falls back to when called without any.
+#[tauri::command]
+pub fn audio_get_options() -> AudioOptions {
+    load_options().unwrap_or_default()
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+use crate::noise::{Noise, NoiseColor};
+
+/// What is added before rounding to 16 bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    /// Plain rounding.
+    #[default]
+    None,
+    /// Triangular noise of ±1 LSB, which makes the rounding error
+    /// independent of the signal.
+    Tpdf,
+    /// TPDF with first-order noise shaping, which moves the noise towards
+    /// high frequencies where it is harder to hear.
+    Shaped,
+}
+
+/// Converts floats to `bits`-wide integers for one channel.
+pub struct Quantizer {
+    bits: u16,
+    dither: Dither,
+    noise: Noise,
+    /// Error of the previous sample in LSBs, fed back when shaping.
+    error: f32,
+}
+
+impl Quantizer {
+    /// Dither is only applied at 16 bits; deeper formats just round.
+    pub fn new(bits: u16, dither: Dither, seed: u32) -> Self {
+        let mut noise = Noise::default();
+        noise.seed(seed);
+        Self {
+            bits,
+            dither: if bits == 16 { dither } else { Dither::None },
+            noise,
+            error: 0.0,
+        }
+    }
+
+    pub fn quantize(&mut self, sample: f32) -> i32 {
+        let max = ((1i64 << (self.bits - 1)) - 1) as f64;
+        let target = sample.clamp(-1.0, 1.0) as f64 * max;
+        let target = match self.dither {
+            Dither::Shaped => target - self.error as f64,
+            Dither::None | Dither::Tpdf => target,
+        };
+        let noise = match self.dither {
+            Dither::None => 0.0,
+            // Two uniform ±0.5 LSB draws sum to a triangle of ±1 LSB.
+            Dither::Tpdf | Dither::Shaped => {
+                (self.noise.next(NoiseColor::White) + self.noise.next(NoiseColor::White)) * 0.5
+            }
+        };
+        let quantized = (target + noise as f64).round().clamp(-max - 1.0, max);
+        // Clipping can leave a large error; don't feed it back.
+        self.error = ((quantized - target) as f32).clamp(-1.5, 1.5);
+        quantized as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A slow sweep over most of the range, landing between integer steps.
+    fn signal() -> impl Iterator<Item = f32> {
+        (0..20_000).map(|i| (i as f32 * 0.000_37).sin() * 0.9)
+    }
+
+    #[test]
+    fn no_dither_rounds_exactly() {
+        let mut quantizer = Quantizer::new(16, Dither::None, 1);
+        for sample in signal() {
+            let expected = (sample as f64 * 32767.0).round() as i32;
+            assert_eq!(quantizer.quantize(sample), expected);
+        }
+    }
+
+    #[test]
+    fn deep_formats_ignore_dither() {
+        for bits in [24, 32] {
+            let mut plain = Quantizer::new(bits, Dither::None, 1);
+            for dither in [Dither::Tpdf, Dither::Shaped] {
+                let mut dithered = Quantizer::new(bits, dither, 1);
+                for sample in signal() {
+                    assert_eq!(dithered.quantize(sample), plain.quantize(sample));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clamps_at_full_scale() {
+        let mut plain = Quantizer::new(16, Dither::None, 1);
+        assert_eq!(plain.quantize(1.5), 32767);
+        assert_eq!(plain.quantize(-1.5), -32767);
+        // Dither and shaped error can pull clipped samples a few LSBs in,
+        // but never past the 16-bit range.
+        for dither in [Dither::Tpdf, Dither::Shaped] {
+            let mut quantizer = Quantizer::new(16, dither, 3);
+            for _ in 0..1000 {
+                assert!((32763..=32767).contains(&quantizer.quantize(1.5)));
+                assert!((-32768..=-32763).contains(&quantizer.quantize(-1.5)));
+            }
+        }
+        assert_eq!(Quantizer::new(24, Dither::None, 1).quantize(2.0), 8_388_607);
+        assert_eq!(Quantizer::new(24, Dither::None, 1).quantize(-2.0), -8_388_607);
+        assert_eq!(Quantizer::new(32, Dither::None, 1).quantize(1.0), i32::MAX);
+    }
+
+    #[test]
+    fn tpdf_noise_stays_within_one_lsb() {
+        let mut quantizer = Quantizer::new(16, Dither::Tpdf, 7);
+        let (mut sum, mut sum_squares, mut count) = (0.0, 0.0, 0.0);
+        for sample in signal() {
+            let target = sample as f64 * 32767.0;
+            let quantized = quantizer.quantize(sample);
+            // The dither moves the value by at most ±1 LSB before rounding.
+            let lowest = (target - 1.0).round() as i32;
+            let highest = (target + 1.0).round() as i32;
+            assert!((lowest..=highest).contains(&quantized), "{target} -> {quantized}");
+            let error = quantized as f64 - target;
+            sum += error;
+            sum_squares += error * error;
+            count += 1.0;
+        }
+        // Unbiased, with the TPDF + rounding variance of 1/6 + 1/12 LSB².
+        assert!((sum / count).abs() < 0.02);
+        assert!((sum_squares / count - 0.25).abs() < 0.02);
+    }
+}
@@ -0,0 +1,417 @@
+use std::cmp::Ordering;
+
+use crate::envelope::EnvelopeParams;
+use crate::filter::{FilterMode, Slope};
+use crate::noise::NoiseColor;
+use crate::oscillator::{Quality, Waveform};
+use crate::smoothing::{self, ParamSmoother};
+use crate::stereo::{self, UnisonLayout, MAX_UNISON};
+use crate::synth::SynthState;
+use crate::voice::Voice;
+
+/// Size of the voice pool; `Global.polyphony` is clamped to it.
+pub const MAX_VOICES: usize = 16;
+/// Glide time in seconds at `Global.glide == 1.0`.
+const MAX_GLIDE_SECONDS: f32 = 2.0;
+
+/// How a new note picks a voice when every voice in the pool is sounding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StealMode {
+    Oldest,
+    Quietest,
+}
+
+impl StealMode {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "quietest" => Self::Quietest,
+            _ => Self::Oldest,
+        }
+    }
+}
+
+/// Snapshot of the `SynthState` fields the engine reads, clamped to safe
+/// ranges. Plain `Copy` data so it can be handed to the audio thread without
+/// allocating.
+#[derive(Debug, Clone, Copy)]
+pub struct Params {
+    pub waveform: Waveform,
+    pub tune: f32,
+    pub level: f32,
+    pub waveform2: Waveform,
+    pub tune2: f32,
+    pub level2: f32,
+    /// Restart oscillator 2 whenever oscillator 1 completes a cycle.
+    pub sync: bool,
+    pub quality: Quality,
+    pub noise: f32,
+    pub noise_color: NoiseColor,
+    pub sub: f32,
+    /// Frequency ratio between the oscillator and the sub: 2 or 4.
+    pub sub_divisor: f32,
+    pub master: f32,
+    pub pan: f32,
+    pub width: f32,
+    /// How far a voice's pitch moves it from `pan`; see [`Voice::next`].
+    pub pan_spread: f32,
+    /// Oscillator copies per voice, `1..=MAX_UNISON`.
+    pub unison: usize,
+    /// Cents either side for the outermost unison copies.
+    pub unison_detune: f32,
+    pub unison_spread: f32,
+    pub cutoff: f32,
+    pub resonance: f32,
+    pub filter_mode: FilterMode,
+    pub filter_slope: Slope,
+    /// Envelope-to-cutoff depth; negative values sweep downwards.
+    pub env_amount: f32,
+    pub drive: f32,
+    pub clip_amount: f32,
+    /// Time constant in seconds for continuous parameter changes.
+    pub smoothing_time: f32,
+    pub envelope: EnvelopeParams,
+    pub mono: bool,
+    pub legato: bool,
+    /// Seconds for a mono glide; zero jumps straight to the new note.
+    pub glide_time: f32,
+    pub polyphony: usize,
+    pub steal: StealMode,
+}
+
+impl Params {
+    pub fn from_state(state: &SynthState) -> Self {
+        Self {
+            waveform: Waveform::from_name(&state.oscillator.waveform),
+            tune: state.oscillator.tune,
+            level: state.oscillator.level.clamp(0.0, 1.0),
+            waveform2: Waveform::from_name(&state.oscillator2.waveform),
+            tune2: state.oscillator2.tune,
+            level2: state.oscillator2.level.clamp(0.0, 1.0),
+            sync: state.oscillator.sync,
+            quality: Quality::from_name(&state.global.quality),
+            noise: state.mixer.noise.clamp(0.0, 1.0),
+            noise_color: NoiseColor::from_name(&state.mixer.noise_color),
+            sub: state.mixer.sub.clamp(0.0, 1.0),
+            sub_divisor: if state.mixer.sub_octave >= 2 { 4.0 } else { 2.0 },
+            master: state.mixer.master.clamp(0.0, 1.0),
+            pan: state.mixer.pan.clamp(-1.0, 1.0),
+            width: state.mixer.width.clamp(0.0, 2.0),
+            pan_spread: state.mixer.pan_spread.clamp(0.0, 1.0),
+            unison: (state.unison.voices as usize).clamp(1, MAX_UNISON),
+            unison_detune: state.unison.detune.clamp(0.0, 100.0),
+            unison_spread: state.unison.spread.clamp(0.0, 1.0),
+            cutoff: state.filter.cutoff.clamp(20.0, 20000.0),
+            resonance: state.filter.resonance.clamp(0.0, 1.0),
+            filter_mode: FilterMode::from_name(&state.filter.mode),
+            filter_slope: Slope::from_db(state.filter.slope),
+            env_amount: state.filter.env_amount.clamp(-1.0, 1.0),
+            drive: state.filter.drive.clamp(0.0, 1.0),
+            clip_amount: state.global.clip_amount.clamp(0.05, 1.0),
+            smoothing_time: state.global.smoothing_ms.clamp(0.0, 1000.0) / 1000.0,
+            envelope: EnvelopeParams::from_state(state),
+            mono: state.global.mono,
+            legato: state.global.legato,
+            // Squared so the short glides most patches want get most of the knob.
+            glide_time: state.global.glide.clamp(0.0, 1.0).powi(2) * MAX_GLIDE_SECONDS,
+            polyphony: (state.global.polyphony as usize).clamp(1, MAX_VOICES),
+            steal: StealMode::from_name(&state.global.voice_steal),
+        }
+    }
+}
+
+/// The voice pool shared by the live stream and the offline renderer.
+///
+/// Both paths feed it parameters with [`Engine::set_state`], notes with
+/// [`Engine::note_on`]/[`Engine::note_off`] and pull audio with
+/// [`Engine::process`], so a render is bit-identical to what the stream plays
+/// for the same changes at the same sample positions.
+pub struct Engine {
+    sample_rate: f32,
+    params: Params,
+    smoother: ParamSmoother,
+    voices: [Voice; MAX_VOICES],
+    /// Keys currently down, most recent last; mono mode falls back through it.
+    held: [u8; MAX_VOICES],
+    held_len: usize,
+    /// Counts note-ons so voices can be ordered by age.
+    clock: u64,
+}
+
+impl Engine {
+    pub fn new(sample_rate: f32, state: &SynthState) -> Self {
+        let params = Params::from_state(state);
+        Self {
+            sample_rate,
+            params,
+            smoother: ParamSmoother::new(&params),
+            voices: [Voice::default(); MAX_VOICES],
+            held: [0; MAX_VOICES],
+            held_len: 0,
+            clock: 0,
+        }
+    }
+
+    /// Continuous parameters ramp to the new values over
+    /// `Global.smoothing_ms` rather than jumping.
+    pub fn set_state(&mut self, state: &SynthState) {
+        self.params = Params::from_state(state);
+        self.smoother.set(&self.params);
+    }
+
+    /// Starts `note` at `velocity` (`0.0..=1.0`); a zero velocity is a note-off.
+    pub fn note_on(&mut self, note: u8, velocity: f32) {
+        let velocity = velocity.clamp(0.0, 1.0);
+        if velocity == 0.0 {
+            self.note_off(note);
+            return;
+        }
+        self.clock += 1;
+        self.push_held(note);
+
+        if self.params.mono {
+            self.mono_note_on(note, velocity);
+            return;
+        }
+        let pool = &self.voices[..self.params.polyphony];
+        let index = pool
+            .iter()
+            .position(|v| v.is_active() && v.note == note)
+            .or_else(|| pool.iter().position(|v| !v.is_active()))
+            .unwrap_or_else(|| self.steal_index());
+        self.voices[index].start(note, velocity, self.clock, false);
+    }
+
+    /// Mono mode plays everything on the first voice. Notes glide from the
+    /// previous pitch; in legato mode only overlapping notes glide, and they
+    /// do so without retriggering the envelope.
+    fn mono_note_on(&mut self, note: u8, velocity: f32) {
+        let voice = &mut self.voices[0];
+        if self.params.legato {
+            if voice.is_held() {
+                voice.retune(note);
+            } else {
+                voice.start(note, velocity, self.clock, false);
+            }
+        } else {
+            let glide = voice.is_active();
+            voice.start(note, velocity, self.clock, glide);
+        }
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        self.remove_held(note);
+        if self.params.mono && self.voices[0].is_held() && self.voices[0].note == note {
+            if let Some(previous) = self.held[..self.held_len].last().copied() {
+                self.voices[0].retune(previous);
+                return;
+            }
+        }
+        for voice in self
+            .voices
+            .iter_mut()
+            .filter(|v| v.is_held() && v.note == note)
+        {
+            voice.release();
+        }
+    }
+
+    /// Fills `left` and `right` (the same length) with samples in
+    /// `-1.0..=1.0`.
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let coeff = smoothing::coefficient(self.params.smoothing_time, self.sample_rate);
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let p = self.smoother.next(&self.params, coeff);
+            let layout = UnisonLayout::new(&p);
+            let (mut mix_l, mut mix_r) = (0.0, 0.0);
+            for voice in self.voices.iter_mut().filter(|v| v.is_active()) {
+                let (vl, vr) = voice.next(&p, &layout, self.sample_rate);
+                mix_l += vl;
+                mix_r += vr;
+            }
+            let gain = p.master * p.clip_amount;
+            let (wide_l, wide_r) = stereo::widen(mix_l * gain, mix_r * gain, p.width);
+            *l = soft_clip(wide_l);
+            *r = soft_clip(wide_r);
+        }
+    }
+
+    /// Picks the voice to cut off when the pool is full. Released voices go
+    /// before held ones so a stolen note is one the player already let go.
+    fn steal_index(&self) -> usize {
+        let candidates = self.voices[..self.params.polyphony].iter().enumerate();
+        let victim = match self.params.steal {
+            StealMode::Oldest => candidates.min_by_key(|(_, v)| (v.is_held(), v.started_at)),
+            StealMode::Quietest => candidates.min_by(|(_, a), (_, b)| {
+                (a.is_held(), a.level())
+                    .partial_cmp(&(b.is_held(), b.level()))
+                    .unwrap_or(Ordering::Equal)
+            }),
+        };
+        victim.map(|(index, _)| index).unwrap_or(0)
+    }
+
+    fn push_held(&mut self, note: u8) {
+        self.remove_held(note);
+        if self.held_len == self.held.len() {
+            self.held.copy_within(1.., 0);
+            self.held_len -= 1;
+        }
+        self.held[self.held_len] = note;
+        self.held_len += 1;
+    }
+
+    fn remove_held(&mut self, note: u8) {
+        if let Some(index) = self.held[..self.held_len].iter().position(|&n| n == note) {
+            self.held.copy_within(index + 1..self.held_len, index);
+            self.held_len -= 1;
+        }
+    }
+}
+
+fn soft_clip(x: f32) -> f32 {
+    x / (1.0 + x.abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 44_100.0;
+
+    fn engine_with(configure: impl FnOnce(&mut SynthState)) -> Engine {
+        let mut state = SynthState::default();
+        configure(&mut state);
+        Engine::new(SAMPLE_RATE, &state)
+    }
+
+    fn run(engine: &mut Engine, samples: usize) {
+        let mut left = vec![0.0; samples];
+        let mut right = vec![0.0; samples];
+        engine.process(&mut left, &mut right);
+    }
+
+    /// Notes of the active voices, sorted.
+    fn sounding(engine: &Engine) -> Vec<u8> {
+        let mut notes: Vec<u8> = engine
+            .voices
+            .iter()
+            .filter(|v| v.is_active())
+            .map(|v| v.note)
+            .collect();
+        notes.sort();
+        notes
+    }
+
+    #[test]
+    fn stays_within_polyphony() {
+        let mut engine = engine_with(|s| s.global.polyphony = 4);
+        for note in 60..66 {
+            engine.note_on(note, 1.0);
+            run(&mut engine, 64);
+        }
+        assert_eq!(sounding(&engine).len(), 4);
+        assert!(engine.voices[4..].iter().all(|v| !v.is_active()));
+
+        // The same key again reuses its voice.
+        engine.note_on(65, 1.0);
+        assert_eq!(sounding(&engine), [62, 63, 64, 65]);
+    }
+
+    #[test]
+    fn steals_the_oldest_voice() {
+        let mut engine = engine_with(|s| {
+            s.global.polyphony = 3;
+            s.global.voice_steal = "oldest".into();
+        });
+        for note in [60, 62, 64] {
+            engine.note_on(note, 1.0);
+            run(&mut engine, 64);
+        }
+        engine.note_on(65, 1.0);
+        assert_eq!(sounding(&engine), [62, 64, 65]);
+
+        // A released voice goes before an older held one.
+        engine.note_off(64);
+        engine.note_on(67, 1.0);
+        assert_eq!(sounding(&engine), [62, 65, 67]);
+    }
+
+    #[test]
+    fn steals_the_quietest_voice() {
+        let mut engine = engine_with(|s| {
+            s.global.polyphony = 2;
+            s.global.voice_steal = "quietest".into();
+        });
+        engine.note_on(60, 1.0);
+        engine.note_on(62, 0.2);
+        run(&mut engine, 2048);
+        engine.note_on(64, 1.0);
+        assert_eq!(sounding(&engine), [60, 64]);
+    }
+
+    #[test]
+    fn mono_falls_back_to_the_last_held_note() {
+        let mut engine = engine_with(|s| s.global.mono = true);
+        for note in [60, 64, 67] {
+            engine.note_on(note, 1.0);
+            run(&mut engine, 64);
+        }
+        assert_eq!(sounding(&engine), [67]);
+
+        engine.note_off(67);
+        assert_eq!(engine.voices[0].note, 64);
+        assert!(engine.voices[0].is_held());
+        // Releasing a key that isn't sounding leaves the voice alone.
+        engine.note_off(60);
+        assert_eq!(engine.voices[0].note, 64);
+        assert!(engine.voices[0].is_held());
+        engine.note_off(64);
+        assert!(!engine.voices[0].is_held());
+        assert_eq!(sounding(&engine), [64]);
+    }
+
+    #[test]
+    fn legato_only_retriggers_detached_notes() {
+        let mut engine = engine_with(|s| {
+            s.global.mono = true;
+            s.global.legato = true;
+        });
+        engine.note_on(60, 1.0);
+        let started = engine.voices[0].started_at;
+        engine.note_on(64, 1.0);
+        assert_eq!(engine.voices[0].note, 64);
+        assert_eq!(engine.voices[0].started_at, started, "overlap retriggered");
+
+        engine.note_off(64);
+        engine.note_off(60);
+        engine.note_on(67, 1.0);
+        assert!(engine.voices[0].started_at > started, "detached note did not retrigger");
+
+        let mut plain = engine_with(|s| s.global.mono = true);
+        plain.note_on(60, 1.0);
+        let started = plain.voices[0].started_at;
+        plain.note_on(64, 1.0);
+        assert!(plain.voices[0].started_at > started);
+    }
+
+    #[test]
+    fn pan_spread_places_voices_by_pitch() {
+        // Left-to-right energy ratio of `note` played on its own.
+        let balance = |pan_spread: f32, note: u8| {
+            let mut engine = engine_with(|s| s.mixer.pan_spread = pan_spread);
+            engine.note_on(note, 1.0);
+            let mut left = vec![0.0; 4096];
+            let mut right = vec![0.0; 4096];
+            engine.process(&mut left, &mut right);
+            let energy = |samples: &[f32]| samples.iter().map(|s| s * s).sum::<f32>();
+            energy(&left) / energy(&right)
+        };
+        let (low, high) = (balance(1.0, 48), balance(1.0, 72));
+        assert!(low > 2.0, "low note not left of centre: {low}");
+        assert!(high < 0.5, "high note not right of centre: {high}");
+
+        for note in [48, 72] {
+            assert!((balance(0.0, note) - 1.0).abs() < 1e-3);
+        }
+    }
+}
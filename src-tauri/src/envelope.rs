@@ -0,0 +1,193 @@
+use crate::synth::SynthState;
+
+/// Shortest segment time in seconds; anything faster clicks.
+const MIN_TIME: f32 = 0.001;
+/// Level below which a releasing envelope is considered finished (-80 dB).
+const SILENCE: f32 = 1.0e-4;
+/// Decay and release times are the time to fall by 60 dB.
+const SIXTY_DB: f32 = 6.907_755;
+
+#[derive(Debug, Clone, Copy)]
+pub struct EnvelopeParams {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl EnvelopeParams {
+    pub fn from_state(state: &SynthState) -> Self {
+        Self {
+            attack: state.envelope.attack.max(MIN_TIME),
+            decay: state.envelope.decay.max(MIN_TIME),
+            sustain: state.envelope.sustain.clamp(0.0, 1.0),
+            release: state.envelope.release.max(MIN_TIME),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Linear-attack, exponential decay/release ADSR generator.
+#[derive(Debug, Clone, Copy)]
+pub struct Adsr {
+    stage: Stage,
+    level: f32,
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Self {
+            stage: Stage::Idle,
+            level: 0.0,
+        }
+    }
+}
+
+impl Adsr {
+    /// Starts the attack from the current level, so retriggering never clicks.
+    pub fn gate_on(&mut self) {
+        self.stage = Stage::Attack;
+    }
+
+    pub fn gate_off(&mut self) {
+        if self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.stage != Stage::Idle
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn next(&mut self, params: &EnvelopeParams, sample_rate: f32) -> f32 {
+        match self.stage {
+            Stage::Idle => {
+                self.level = 0.0;
+            }
+            Stage::Attack => {
+                self.level += 1.0 / (params.attack * sample_rate);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                let coeff = (-SIXTY_DB / (params.decay * sample_rate)).exp();
+                self.level = params.sustain + (self.level - params.sustain) * coeff;
+                if (self.level - params.sustain).abs() < SILENCE {
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => {
+                self.level = params.sustain;
+            }
+            Stage::Release => {
+                let coeff = (-SIXTY_DB / (params.release * sample_rate)).exp();
+                self.level *= coeff;
+                if self.level < SILENCE {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+        self.level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 1000.0;
+
+    fn params() -> EnvelopeParams {
+        EnvelopeParams {
+            attack: 0.1,
+            decay: 0.2,
+            sustain: 0.5,
+            release: 0.3,
+        }
+    }
+
+    /// Runs until `done` and returns how many samples that took.
+    fn run_until(adsr: &mut Adsr, done: impl Fn(&Adsr) -> bool) -> usize {
+        let mut samples = 0;
+        while !done(adsr) {
+            adsr.next(&params(), SAMPLE_RATE);
+            samples += 1;
+            assert!(samples < 10_000, "envelope never got there");
+        }
+        samples
+    }
+
+    #[test]
+    fn attack_reaches_the_peak_in_the_attack_time() {
+        let mut adsr = Adsr::default();
+        adsr.gate_on();
+        let samples = run_until(&mut adsr, |a| a.level() >= 1.0);
+        assert!((99..=101).contains(&samples), "{samples} samples");
+    }
+
+    #[test]
+    fn decays_to_and_holds_the_sustain_level() {
+        let mut adsr = Adsr::default();
+        adsr.gate_on();
+        for _ in 0..1000 {
+            adsr.next(&params(), SAMPLE_RATE);
+        }
+        assert_eq!(adsr.stage, Stage::Sustain);
+        assert_eq!(adsr.level(), 0.5);
+        assert_eq!(adsr.next(&params(), SAMPLE_RATE), 0.5);
+    }
+
+    #[test]
+    fn release_falls_to_idle() {
+        let mut adsr = Adsr::default();
+        adsr.gate_on();
+        run_until(&mut adsr, |a| a.stage == Stage::Sustain);
+        adsr.gate_off();
+        // Falling 60 dB takes the release time; from 0.5 to -80 dB is 74 dB.
+        let expected = 0.3 * (0.5 / SILENCE).ln() / SIXTY_DB * SAMPLE_RATE;
+        let samples = run_until(&mut adsr, |a| !a.is_active());
+        assert!(
+            (samples as f32 - expected).abs() <= 2.0,
+            "{samples} samples"
+        );
+        assert_eq!(adsr.level(), 0.0);
+        assert_eq!(adsr.next(&params(), SAMPLE_RATE), 0.0);
+    }
+
+    #[test]
+    fn retriggering_during_release_continues_from_the_current_level() {
+        let mut adsr = Adsr::default();
+        adsr.gate_on();
+        run_until(&mut adsr, |a| a.stage == Stage::Sustain);
+        adsr.gate_off();
+        for _ in 0..50 {
+            adsr.next(&params(), SAMPLE_RATE);
+        }
+        let released = adsr.level();
+        assert!(released > 0.1 && released < 0.5);
+
+        adsr.gate_on();
+        let next = adsr.next(&params(), SAMPLE_RATE);
+        assert!(
+            next > released && next - released < 0.011,
+            "{released} -> {next}"
+        );
+        run_until(&mut adsr, |a| a.level() >= 1.0);
+        assert_eq!(adsr.stage, Stage::Decay);
+    }
+}
@@ -0,0 +1,556 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Samples per channel in each frame.
+const BLOCK_SIZE: usize = 4096;
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_PARTITION_ORDER: u32 = 8;
+/// Largest Rice parameter the 4-bit coding method can signal; above it the
+/// 5-bit method is used.
+const MAX_RICE_PARAM: u32 = 14;
+const MAX_RICE2_PARAM: u32 = 30;
+const STREAMINFO_LEN: usize = 34;
+
+/// Streaming FLAC encoder using the fixed predictors, partitioned Rice
+/// residuals and stereo decorrelation. No LPC, so files come out somewhat
+/// larger than the reference encoder's, but they decode anywhere.
+///
+/// Samples are written interleaved; [`FlacWriter::finish`] seeks back to
+/// fill in the stream info, so the output has to be seekable.
+pub struct FlacWriter<W: Write + Seek> {
+    out: W,
+    /// Position of the `fLaC` marker.
+    start: u64,
+    sample_rate: u32,
+    bits: u32,
+    /// Samples of the current block, per channel.
+    pending: Vec<Vec<i64>>,
+    next_channel: usize,
+    frame_number: u32,
+    total_samples: u64,
+    min_frame: u32,
+    max_frame: u32,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    /// `bits` is 16 or 24. `tags` become Vorbis comments (`TITLE`, ...).
+    pub fn new(
+        mut out: W,
+        sample_rate: u32,
+        channels: u16,
+        bits: u16,
+        tags: &[(&str, String)],
+    ) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        if !matches!(bits, 16 | 24) {
+            return Err(invalid(format!("FLAC export needs 16 or 24-bit samples, not {bits}")));
+        }
+        if !(1..=8).contains(&channels) {
+            return Err(invalid(format!("FLAC supports 1 to 8 channels, not {channels}")));
+        }
+        if sample_rate == 0 || sample_rate >= 1 << 20 {
+            return Err(invalid(format!("sample rate {sample_rate} can't be stored in FLAC")));
+        }
+
+        let start = out.stream_position()?;
+        out.write_all(b"fLaC")?;
+        write_block_header(&mut out, false, 0, STREAMINFO_LEN)?;
+        out.write_all(&[0; STREAMINFO_LEN])?;
+        let comments = vorbis_comments(tags);
+        write_block_header(&mut out, true, 4, comments.len())?;
+        out.write_all(&comments)?;
+
+        Ok(Self {
+            out,
+            start,
+            sample_rate,
+            bits: bits as u32,
+            pending: vec![Vec::with_capacity(BLOCK_SIZE); channels as usize],
+            next_channel: 0,
+            frame_number: 0,
+            total_samples: 0,
+            min_frame: u32::MAX,
+            max_frame: 0,
+        })
+    }
+
+    pub fn write_sample(&mut self, sample: i32) -> io::Result<()> {
+        self.pending[self.next_channel].push(sample as i64);
+        self.next_channel = (self.next_channel + 1) % self.pending.len();
+        if self.next_channel == 0 && self.pending[0].len() == BLOCK_SIZE {
+            self.flush_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last partial frame and the stream info.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.next_channel != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FLAC stream ended mid-frame",
+            ));
+        }
+        if !self.pending[0].is_empty() {
+            self.flush_frame()?;
+        }
+        let info = self.stream_info();
+        self.out.seek(SeekFrom::Start(self.start + 8))?;
+        self.out.write_all(&info)?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn stream_info(&self) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        // The last frame may be shorter; the stream info excludes it.
+        w.write(BLOCK_SIZE as u64, 16);
+        w.write(BLOCK_SIZE as u64, 16);
+        w.write(if self.max_frame == 0 { 0 } else { self.min_frame as u64 }, 24);
+        w.write(self.max_frame as u64, 24);
+        w.write(self.sample_rate as u64, 20);
+        w.write(self.pending.len() as u64 - 1, 3);
+        w.write(self.bits as u64 - 1, 5);
+        w.write(self.total_samples, 36);
+        // No MD5 signature; all zeros means "not computed".
+        w.write(0, 32);
+        w.write(0, 32);
+        w.write(0, 32);
+        w.write(0, 32);
+        w.into_bytes()
+    }
+
+    fn flush_frame(&mut self) -> io::Result<()> {
+        let frame = encode_frame(&self.pending, self.bits, self.frame_number);
+        self.out.write_all(&frame)?;
+        self.min_frame = self.min_frame.min(frame.len() as u32);
+        self.max_frame = self.max_frame.max(frame.len() as u32);
+        self.total_samples += self.pending[0].len() as u64;
+        self.frame_number += 1;
+        for channel in &mut self.pending {
+            channel.clear();
+        }
+        Ok(())
+    }
+}
+
+fn write_block_header(out: &mut impl Write, last: bool, kind: u8, len: usize) -> io::Result<()> {
+    let len = len as u32;
+    out.write_all(&[
+        (last as u8) << 7 | kind,
+        (len >> 16) as u8,
+        (len >> 8) as u8,
+        len as u8,
+    ])
+}
+
+pub fn vorbis_comments(tags: &[(&str, String)]) -> Vec<u8> {
+    let vendor = concat!("Andromeda ", env!("CARGO_PKG_VERSION"));
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(vendor.as_bytes());
+    bytes.extend_from_slice(&(tags.len() as u32).to_le_bytes());
+    for (key, value) in tags {
+        let comment = format!("{key}={value}");
+        bytes.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(comment.as_bytes());
+    }
+    bytes
+}
+
+/// How a stereo pair is stored; the FLAC channel assignment codes.
+#[derive(Clone, Copy)]
+enum Stereo {
+    Independent,
+    LeftSide = 8,
+    RightSide = 9,
+    MidSide = 10,
+}
+
+fn encode_frame(channels: &[Vec<i64>], bits: u32, frame_number: u32) -> Vec<u8> {
+    let block = channels[0].len();
+    // Each entry: samples, their bit depth, and the best way to code them.
+    let mut subframes: Vec<(Vec<i64>, u32, Subframe)> = Vec::with_capacity(channels.len());
+    let mut assignment = channels.len() as u64 - 1;
+
+    if channels.len() == 2 {
+        let (left, right) = (&channels[0], &channels[1]);
+        let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+        let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+        let [l, r, s, m] = [
+            analyze(left, bits),
+            analyze(right, bits),
+            analyze(&side, bits + 1),
+            analyze(&mid, bits),
+        ];
+        let stereo = [
+            (Stereo::Independent, l.bits + r.bits),
+            (Stereo::LeftSide, l.bits + s.bits),
+            (Stereo::RightSide, s.bits + r.bits),
+            (Stereo::MidSide, m.bits + s.bits),
+        ]
+        .into_iter()
+        .min_by_key(|(_, cost)| *cost)
+        .map(|(stereo, _)| stereo)
+        .unwrap_or(Stereo::Independent);
+        let (left, right) = (left.clone(), right.clone());
+        subframes = match stereo {
+            Stereo::Independent => vec![(left, bits, l), (right, bits, r)],
+            Stereo::LeftSide => vec![(left, bits, l), (side, bits + 1, s)],
+            Stereo::RightSide => vec![(side, bits + 1, s), (right, bits, r)],
+            Stereo::MidSide => vec![(mid, bits, m), (side, bits + 1, s)],
+        };
+        if !matches!(stereo, Stereo::Independent) {
+            assignment = stereo as u64;
+        }
+    } else {
+        for channel in channels {
+            let subframe = analyze(channel, bits);
+            subframes.push((channel.clone(), bits, subframe));
+        }
+    }
+
+    let mut w = BitWriter::default();
+    w.write(0b11_1111_1111_1110, 14);
+    w.write(0, 1); // reserved
+    w.write(0, 1); // fixed block size
+    w.write(0b0111, 4); // 16-bit block size follows the header
+    w.write(0, 4); // sample rate from the stream info
+    w.write(assignment, 4);
+    w.write(if bits == 24 { 0b110 } else { 0b100 }, 3);
+    w.write(0, 1); // reserved
+    write_utf8(&mut w, frame_number);
+    w.write(block as u64 - 1, 16);
+    let crc = crc8(&w.bytes);
+    w.write(crc as u64, 8);
+
+    for (samples, bits, subframe) in &subframes {
+        write_subframe(&mut w, samples, *bits, subframe);
+    }
+    let mut bytes = w.into_bytes();
+    let crc = crc16(&bytes);
+    bytes.extend_from_slice(&crc.to_be_bytes());
+    bytes
+}
+
+enum Kind {
+    Constant,
+    Verbatim,
+    Fixed { order: usize, rice: RicePlan },
+}
+
+struct Subframe {
+    kind: Kind,
+    /// Estimated size in bits, for choosing between codings.
+    bits: u64,
+}
+
+struct RicePlan {
+    partition_order: u32,
+    params: Vec<u32>,
+    /// Uses the 5-bit parameter coding method.
+    wide: bool,
+}
+
+/// Picks the smallest of constant, verbatim and fixed orders 0..=4.
+fn analyze(samples: &[i64], bits: u32) -> Subframe {
+    let n = samples.len();
+    if samples.iter().all(|&s| s == samples[0]) {
+        return Subframe {
+            kind: Kind::Constant,
+            bits: 8 + bits as u64,
+        };
+    }
+    let mut best = Subframe {
+        kind: Kind::Verbatim,
+        bits: 8 + n as u64 * bits as u64,
+    };
+    for order in 0..=MAX_FIXED_ORDER.min(n - 1) {
+        let residual = fixed_residual(samples, order);
+        if let Some((rice, cost)) = plan_rice(&residual, n, order) {
+            let total = 8 + order as u64 * bits as u64 + cost;
+            if total < best.bits {
+                best = Subframe {
+                    kind: Kind::Fixed { order, rice },
+                    bits: total,
+                };
+            }
+        }
+    }
+    best
+}
+
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    samples
+        .windows(order + 1)
+        .map(|w| match order {
+            0 => w[0],
+            1 => w[1] - w[0],
+            2 => w[2] - 2 * w[1] + w[0],
+            3 => w[3] - 3 * w[2] + 3 * w[1] - w[0],
+            _ => w[4] - 4 * w[3] + 6 * w[2] - 4 * w[1] + w[0],
+        })
+        .collect()
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Estimated bits for `count` values summing to `sum` (zigzagged) at Rice
+/// parameter `k`.
+fn rice_cost(sum: u64, count: u64, k: u32) -> u64 {
+    count * (k as u64 + 1) + (sum >> k)
+}
+
+fn best_param(sum: u64, count: u64) -> u32 {
+    if count == 0 || sum < count {
+        return 0;
+    }
+    let guess = 63 - (sum / count).leading_zeros();
+    (guess.saturating_sub(1)..=(guess + 1).min(MAX_RICE2_PARAM))
+        .min_by_key(|&k| rice_cost(sum, count, k))
+        .unwrap_or(0)
+}
+
+/// Chooses the partition order and per-partition parameters with the
+/// smallest estimated size. `None` if the residual can't be coded.
+fn plan_rice(residual: &[i64], block: usize, order: usize) -> Option<(RicePlan, u64)> {
+    let mut best: Option<(RicePlan, u64)> = None;
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partitions = 1usize << partition_order;
+        if !block.is_multiple_of(partitions) || block / partitions <= order {
+            break;
+        }
+        let size = block / partitions;
+        let mut params = Vec::with_capacity(partitions);
+        let mut cost = 0;
+        for i in 0..partitions {
+            let start = if i == 0 { 0 } else { i * size - order };
+            let end = (i + 1) * size - order;
+            let part = &residual[start..end];
+            let sum = part.iter().map(|&r| zigzag(r)).sum::<u64>();
+            let k = best_param(sum, part.len() as u64);
+            cost += rice_cost(sum, part.len() as u64, k);
+            params.push(k);
+        }
+        let wide = params.iter().any(|&k| k > MAX_RICE_PARAM);
+        cost += 2 + 4 + partitions as u64 * if wide { 5 } else { 4 };
+        if best.as_ref().is_none_or(|(_, best_cost)| cost < *best_cost) {
+            best = Some((
+                RicePlan {
+                    partition_order,
+                    params,
+                    wide,
+                },
+                cost,
+            ));
+        }
+    }
+    best
+}
+
+fn write_subframe(w: &mut BitWriter, samples: &[i64], bits: u32, subframe: &Subframe) {
+    // Zero padding bit, 6-bit type, no wasted bits.
+    match &subframe.kind {
+        Kind::Constant => {
+            w.write(0b0000_0000, 8);
+            w.write_signed(samples[0], bits);
+        }
+        Kind::Verbatim => {
+            w.write(0b0000_0010, 8);
+            for &sample in samples {
+                w.write_signed(sample, bits);
+            }
+        }
+        Kind::Fixed { order, rice } => {
+            w.write((0b1000 | *order as u64) << 1, 8);
+            for &sample in &samples[..*order] {
+                w.write_signed(sample, bits);
+            }
+            let residual = fixed_residual(samples, *order);
+            let (param_bits, escape) = if rice.wide { (5, 1) } else { (4, 0) };
+            w.write(escape, 2);
+            w.write(rice.partition_order as u64, 4);
+            let size = samples.len() >> rice.partition_order;
+            for (i, &k) in rice.params.iter().enumerate() {
+                let start = if i == 0 { 0 } else { i * size - order };
+                let end = (i + 1) * size - order;
+                w.write(k as u64, param_bits);
+                for &r in &residual[start..end] {
+                    let u = zigzag(r);
+                    w.write_unary(u >> k);
+                    w.write(u, k);
+                }
+            }
+        }
+    }
+}
+
+/// Frame numbers use the UTF-8 style variable-length coding.
+fn write_utf8(w: &mut BitWriter, value: u32) {
+    if value < 0x80 {
+        w.write(value as u64, 8);
+        return;
+    }
+    let len = match value {
+        0..=0x7FF => 2,
+        0x800..=0xFFFF => 3,
+        0x1_0000..=0x1F_FFFF => 4,
+        0x20_0000..=0x3FF_FFFF => 5,
+        _ => 6,
+    };
+    let lead = (0xFF00u32 >> len) & 0xFF;
+    w.write((lead | value >> (6 * (len - 1))) as u64 & 0xFF, 8);
+    for i in (0..len - 1).rev() {
+        w.write((0x80 | (value >> (6 * i)) & 0x3F) as u64, 8);
+    }
+}
+
+/// MSB-first bit packer.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    /// Appends the low `bits` (at most 32) bits of `value`.
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+        self.acc = (self.acc << bits) | (value & ((1 << bits) - 1));
+        self.len += bits;
+        while self.len >= 8 {
+            self.len -= 8;
+            self.bytes.push((self.acc >> self.len) as u8);
+        }
+        self.acc &= (1 << self.len) - 1;
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    /// `zeros` zero bits followed by a one.
+    fn write_unary(&mut self, zeros: u64) {
+        let mut zeros = zeros;
+        while zeros >= 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+        self.write(1, zeros as u32 + 1);
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.write(0, 8 - self.len);
+        }
+        self.bytes
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Encodes `frames` frames from `sample(frame, channel)` and checks the
+    /// decoder returns them unchanged.
+    fn roundtrip(channels: u16, bits: u16, frames: usize, sample: impl Fn(usize, usize) -> i32) {
+        let tags = [("TITLE", "Round trip".to_string())];
+        let mut writer = FlacWriter::new(Cursor::new(Vec::new()), 48_000, channels, bits, &tags)
+            .expect("valid format");
+        let mut input = Vec::new();
+        for frame in 0..frames {
+            for channel in 0..channels as usize {
+                let value = sample(frame, channel);
+                input.push(value);
+                writer.write_sample(value).unwrap();
+            }
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let mut reader = claxon::FlacReader::new(Cursor::new(bytes)).expect("decodable stream");
+        let info = reader.streaminfo();
+        assert_eq!(info.samples, Some(frames as u64));
+        assert_eq!(info.channels, channels as u32);
+        assert_eq!(info.bits_per_sample, bits as u32);
+        assert_eq!(reader.get_tag("TITLE").next(), Some("Round trip"));
+        let output: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert!(
+            output == input,
+            "{channels} channels at {bits} bits, {frames} frames"
+        );
+    }
+
+    #[test]
+    fn decodes_bit_exact() {
+        let mut seed = 1u32;
+        let noise: Vec<i32> = (0..4096)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as i32 - (1 << 23)
+            })
+            .collect();
+        // Two full blocks and a partial one, and a stream shorter than a block.
+        for frames in [2 * BLOCK_SIZE + 123, 5] {
+            for bits in [16u16, 24] {
+                let full_scale = (1i32 << (bits - 1)) - 1;
+                for channels in [1u16, 2] {
+                    roundtrip(channels, bits, frames, |i, c| {
+                        ((i as f64 * 0.01 + c as f64).sin() * full_scale as f64 * 0.8) as i32
+                    });
+                    roundtrip(channels, bits, frames, |i, c| {
+                        noise[(i * 2 + c) % noise.len()] >> (24 - bits)
+                    });
+                    roundtrip(channels, bits, frames, |_, _| -7);
+                    // Opposite full-scale channels need the widest side channel.
+                    roundtrip(channels, bits, frames, |i, c| {
+                        if (i + c) % 2 == 0 {
+                            full_scale
+                        } else {
+                            -full_scale - 1
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_formats() {
+        let new = |channels, bits| {
+            FlacWriter::new(Cursor::new(Vec::new()), 48_000, channels, bits, &[])
+        };
+        assert!(new(2, 32).is_err());
+        assert!(new(0, 16).is_err());
+        assert!(new(2, 16).is_ok());
+    }
+}
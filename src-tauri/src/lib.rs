@@ -1,13 +1,37 @@
 mod audio;
 mod ai;
 mod automation;
+mod destination;
+mod devices;
+mod dither;
+mod dsp;
+mod envelope;
+mod filter;
+mod flac;
+mod metadata;
+mod noise;
+mod ogg;
+mod oscillator;
+mod params;
 mod render;
+mod smoothing;
+mod stereo;
 mod synth;
+mod voice;
+mod vorbis;
 
-use audio::{audio_is_running, audio_start, audio_stop, AudioEngine};
+use audio::{
+    audio_is_running, audio_start, audio_stop, automation_play, automation_stop, note_off,
+    note_on, AudioEngine,
+};
 use ai::ai_generate_automation;
-use render::render_sample;
-use synth::{synth_get_state, synth_reset, synth_set_state, SynthEngine};
+use automation::automation_validate;
+use devices::{audio_get_options, audio_list_devices, audio_list_hosts};
+use render::{render_cancel, render_read_metadata, render_sample, RenderJobs};
+use synth::{
+    synth_get_parameter, synth_get_state, synth_list_parameters, synth_reset,
+    synth_set_parameter, synth_set_state, SynthEngine,
+};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -15,15 +39,29 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .manage(SynthEngine::default())
         .manage(AudioEngine::default())
+        .manage(RenderJobs::default())
         .invoke_handler(tauri::generate_handler![
             synth_get_state,
             synth_set_state,
             synth_reset,
+            synth_list_parameters,
+            synth_get_parameter,
+            synth_set_parameter,
             audio_start,
             audio_stop,
             audio_is_running,
+            audio_list_hosts,
+            audio_list_devices,
+            audio_get_options,
+            note_on,
+            note_off,
             render_sample,
-            ai_generate_automation
+            render_cancel,
+            render_read_metadata,
+            ai_generate_automation,
+            automation_validate,
+            automation_play,
+            automation_stop
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,258 @@
+use crate::automation::AutomationEvent;
+use crate::synth::SynthState;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Chunk holding a JSON [`RenderMetadata`].
+const PATCH_CHUNK: &[u8; 4] = b"anmd";
+/// Bumped when [`RenderMetadata`] changes incompatibly.
+pub const METADATA_VERSION: u32 = 1;
+const SOFTWARE: &str = concat!("Andromeda ", env!("CARGO_PKG_VERSION"));
+
+/// Everything needed to render a sample again, stored in its `anmd` chunk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderMetadata {
+    pub version: u32,
+    pub preset_name: Option<String>,
+    /// The patch at the start of the render, before any automation.
+    pub state: SynthState,
+    pub events: Vec<AutomationEvent>,
+    pub note: u8,
+    pub velocity: f32,
+    pub duration_ms: u64,
+    pub gate_ms: u64,
+    pub sample_rate: u32,
+}
+
+impl RenderMetadata {
+    fn comment(&self) -> String {
+        format!(
+            "note {}, velocity {:.2}, {} ms",
+            self.note, self.velocity, self.duration_ms
+        )
+    }
+}
+
+/// Format details for the `bext` coding history.
+pub struct Encoding {
+    pub bits: u16,
+    pub channels: u16,
+}
+
+/// Appends `LIST/INFO`, `bext` and `anmd` chunks to a finished WAV file and
+/// fixes up the RIFF size. Readers find chunks by walking the list, so they
+/// don't need to come before `data`.
+pub fn embed(
+    path: &Path,
+    metadata: &RenderMetadata,
+    encoding: &Encoding,
+    created: DateTime<Local>,
+) -> Result<(), String> {
+    let patch =
+        serde_json::to_vec(metadata).map_err(|e| format!("failed to encode metadata: {e}"))?;
+    let title = metadata.preset_name.as_deref().unwrap_or("untitled");
+    let date = created.format("%Y-%m-%d").to_string();
+    let comment = metadata.comment();
+
+    let mut info = b"INFO".to_vec();
+    for (id, value) in [
+        (b"INAM", title),
+        (b"ISFT", SOFTWARE),
+        (b"ICRD", date.as_str()),
+        (b"ICMT", comment.as_str()),
+    ] {
+        let mut text = value.as_bytes().to_vec();
+        text.push(0);
+        push_chunk(&mut info, id, &text);
+    }
+
+    let mut chunks = Vec::new();
+    push_chunk(&mut chunks, b"LIST", &info);
+    push_chunk(&mut chunks, b"bext", &bext(metadata, encoding, created));
+    push_chunk(&mut chunks, PATCH_CHUNK, &patch);
+
+    let fail = |e: std::io::Error| format!("failed to write metadata to {}: {e}", path.display());
+    let mut file = File::options().read(true).write(true).open(path).map_err(fail)?;
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header).map_err(fail)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return Err(format!("{} is not a WAV file", path.display()));
+    }
+    let mut end = file.seek(SeekFrom::End(0)).map_err(fail)?;
+    // The data chunk isn't padded when it has an odd length.
+    if end % 2 == 1 {
+        file.write_all(&[0]).map_err(fail)?;
+        end += 1;
+    }
+    file.write_all(&chunks).map_err(fail)?;
+    let riff_size = u32::try_from(end + chunks.len() as u64 - 8)
+        .map_err(|_| format!("{} is too large for RIFF metadata", path.display()))?;
+    file.seek(SeekFrom::Start(4)).map_err(fail)?;
+    file.write_all(&riff_size.to_le_bytes()).map_err(fail)?;
+    file.flush().map_err(fail)
+}
+
+/// Broadcast Wave extension, version 1 layout (EBU Tech 3285).
+fn bext(metadata: &RenderMetadata, encoding: &Encoding, created: DateTime<Local>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(602 + 64);
+    bytes.extend(ascii(&metadata.comment(), 256));
+    bytes.extend(ascii("Andromeda", 32));
+    bytes.extend(ascii(metadata.preset_name.as_deref().unwrap_or(""), 32));
+    bytes.extend(ascii(&created.format("%Y-%m-%d").to_string(), 10));
+    bytes.extend(ascii(&created.format("%H:%M:%S").to_string(), 8));
+    bytes.extend(0u64.to_le_bytes()); // time reference
+    bytes.extend(1u16.to_le_bytes()); // version
+    bytes.extend([0; 64]); // UMID
+    bytes.extend([0; 190]); // reserved
+    let mode = if encoding.channels == 1 { "mono" } else { "stereo" };
+    bytes.extend(
+        format!(
+            "A=PCM,F={},W={},M={mode},T={SOFTWARE}\r\n",
+            metadata.sample_rate, encoding.bits
+        )
+        .bytes(),
+    );
+    bytes
+}
+
+/// `text` as NUL-padded ASCII of exactly `len` bytes.
+fn ascii(text: &str, len: usize) -> Vec<u8> {
+    let mut bytes: Vec<u8> = text
+        .chars()
+        .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+        .take(len)
+        .collect();
+    bytes.resize(len, 0);
+    bytes
+}
+
+fn push_chunk(out: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Reads the `anmd` chunk of a WAV written by `render_sample`.
+pub fn read(path: &Path) -> Result<RenderMetadata, String> {
+    let fail = |e: std::io::Error| format!("failed to read {}: {e}", path.display());
+    let mut file = File::open(path).map_err(fail)?;
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header).map_err(fail)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return Err(format!("{} is not a WAV file", path.display()));
+    }
+    let len = file.metadata().map_err(fail)?.len();
+    let mut position = 12u64;
+    while position + 8 <= len {
+        let mut chunk = [0u8; 8];
+        file.read_exact(&mut chunk).map_err(fail)?;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+        if &chunk[..4] == PATCH_CHUNK {
+            let mut body = vec![0; size.min(len - position - 8) as usize];
+            file.read_exact(&mut body).map_err(fail)?;
+            let metadata: RenderMetadata = serde_json::from_slice(&body)
+                .map_err(|e| format!("invalid metadata in {}: {e}", path.display()))?;
+            if metadata.version > METADATA_VERSION {
+                return Err(format!(
+                    "{} was rendered by a newer version (metadata v{})",
+                    path.display(),
+                    metadata.version
+                ));
+            }
+            return Ok(metadata);
+        }
+        position += 8 + size + size % 2;
+        file.seek(SeekFrom::Start(position)).map_err(fail)?;
+    }
+    Err(format!("{} has no Andromeda metadata", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automation::Curve;
+    use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+    fn metadata() -> RenderMetadata {
+        let mut state = SynthState::default();
+        state.filter.cutoff = 1234.5;
+        RenderMetadata {
+            version: METADATA_VERSION,
+            preset_name: Some("Glass Pad".into()),
+            state,
+            events: vec![AutomationEvent {
+                time_ms: 12.5,
+                path: "filter.cutoff".into(),
+                value: serde_json::json!(800.0),
+                curve: Curve::Exponential,
+            }],
+            note: 60,
+            velocity: 0.8,
+            duration_ms: 1000,
+            gate_ms: 600,
+            sample_rate: 44_100,
+        }
+    }
+
+    #[test]
+    fn round_trips_through_a_wav() {
+        let dir = std::env::temp_dir().join(format!("andromeda-metadata-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // 24-bit mono with three frames leaves an odd-length data chunk.
+        for (bits, channels, frames) in [(24u16, 1u16, 3i32), (16, 2, 100)] {
+            let path = dir.join(format!("{bits}-{channels}.wav"));
+            let spec = WavSpec {
+                channels,
+                sample_rate: 44_100,
+                bits_per_sample: bits,
+                sample_format: SampleFormat::Int,
+            };
+            let samples: Vec<i32> = (0..frames * channels as i32).map(|i| i * 3 - 50).collect();
+            let mut writer = WavWriter::create(&path, spec).unwrap();
+            for &sample in &samples {
+                writer.write_sample(sample).unwrap();
+            }
+            writer.finalize().unwrap();
+
+            let written = metadata();
+            embed(&path, &written, &Encoding { bits, channels }, Local::now()).unwrap();
+
+            let bytes = std::fs::read(&path).unwrap();
+            let riff_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+            assert_eq!(riff_size as usize, bytes.len() - 8);
+            assert_eq!(bytes.len() % 2, 0);
+            let reader = WavReader::open(&path).unwrap();
+            assert_eq!(reader.spec(), spec);
+            let read_back: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+            assert_eq!(read_back, samples);
+
+            let json = |m: &RenderMetadata| serde_json::to_value(m).unwrap();
+            assert_eq!(json(&read(&path).unwrap()), json(&written));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_files_without_metadata() {
+        let dir = std::env::temp_dir().join(format!("andromeda-plain-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plain.wav");
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        WavWriter::create(&path, spec).unwrap().finalize().unwrap();
+        assert!(read(&path).unwrap_err().contains("no Andromeda metadata"));
+        std::fs::write(&path, b"not a wav file").unwrap();
+        assert!(read(&path).unwrap_err().contains("not a WAV"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseColor {
+    White,
+    Pink,
+}
+
+impl NoiseColor {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "pink" => Self::Pink,
+            _ => Self::White,
+        }
+    }
+}
+
+/// Xorshift white noise with an optional pinking filter. Seeded explicitly so
+/// renders are reproducible.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Noise {
+    state: u32,
+    b0: f32,
+    b1: f32,
+    b2: f32,
+}
+
+impl Noise {
+    pub fn seed(&mut self, seed: u32) {
+        // Xorshift never leaves zero, so keep the state odd.
+        self.state = seed.wrapping_mul(0x9E37_79B9) | 1;
+    }
+
+    pub fn next(&mut self, color: NoiseColor) -> f32 {
+        let white = self.white();
+        match color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => {
+                // Paul Kellet's economy filter: -3 dB/octave within 0.5 dB
+                // above ~100 Hz at 44.1 kHz.
+                self.b0 = 0.99765 * self.b0 + white * 0.099_046;
+                self.b1 = 0.96300 * self.b1 + white * 0.296_516_4;
+                self.b2 = 0.57000 * self.b2 + white * 1.052_691_3;
+                (self.b0 + self.b1 + self.b2 + white * 0.1848) * 0.25
+            }
+        }
+    }
+
+    fn white(&mut self) -> f32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        (x as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
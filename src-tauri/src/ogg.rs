@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+
+/// Pages are closed once they hold this much data.
+const PAGE_TARGET: usize = 4096;
+const MAX_SEGMENTS: usize = 255;
+
+const CONTINUED: u8 = 0x01;
+const FIRST_PAGE: u8 = 0x02;
+const LAST_PAGE: u8 = 0x04;
+
+/// Splits packets of one logical stream into Ogg pages.
+pub struct PageWriter<W: Write> {
+    out: W,
+    serial: u32,
+    sequence: u32,
+    /// Lacing values and data of the page being built.
+    segments: Vec<u8>,
+    data: Vec<u8>,
+    /// Granule position of the last packet that ends on this page, or -1.
+    granule: i64,
+    /// The page starts with the rest of a packet from the previous one.
+    continued: bool,
+}
+
+impl<W: Write> PageWriter<W> {
+    pub fn new(out: W, serial: u32) -> Self {
+        Self {
+            out,
+            serial,
+            sequence: 0,
+            segments: Vec::with_capacity(MAX_SEGMENTS),
+            data: Vec::with_capacity(PAGE_TARGET + MAX_SEGMENTS * 255),
+            granule: -1,
+            continued: false,
+        }
+    }
+
+    /// Adds `packet`, which ends at sample `granule`. With `flush` the page
+    /// is closed after it, so the next packet starts a new one; `last`
+    /// flushes and marks the end of the stream.
+    pub fn write_packet(
+        &mut self,
+        packet: &[u8],
+        granule: u64,
+        flush: bool,
+        last: bool,
+    ) -> io::Result<()> {
+        let mut rest = packet;
+        loop {
+            if self.segments.len() == MAX_SEGMENTS {
+                self.write_page(false)?;
+                self.continued = rest.len() < packet.len();
+            }
+            let len = rest.len().min(255);
+            self.segments.push(len as u8);
+            self.data.extend_from_slice(&rest[..len]);
+            rest = &rest[len..];
+            // A packet ends with the first segment shorter than 255 bytes.
+            if len < 255 {
+                break;
+            }
+        }
+        self.granule = granule as i64;
+        if flush || last || self.data.len() >= PAGE_TARGET {
+            self.write_page(last)?;
+            self.continued = false;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_page(&mut self, last: bool) -> io::Result<()> {
+        let mut flags = 0;
+        if self.continued {
+            flags |= CONTINUED;
+        }
+        if self.sequence == 0 {
+            flags |= FIRST_PAGE;
+        }
+        if last {
+            flags |= LAST_PAGE;
+        }
+        let mut page = Vec::with_capacity(27 + self.segments.len() + self.data.len());
+        page.extend_from_slice(b"OggS");
+        page.push(0);
+        page.push(flags);
+        page.extend_from_slice(&self.granule.to_le_bytes());
+        page.extend_from_slice(&self.serial.to_le_bytes());
+        page.extend_from_slice(&self.sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(self.segments.len() as u8);
+        page.extend_from_slice(&self.segments);
+        page.extend_from_slice(&self.data);
+        let crc = crc32(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        self.out.write_all(&page)?;
+
+        self.sequence += 1;
+        self.granule = -1;
+        self.segments.clear();
+        self.data.clear();
+        Ok(())
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u32) << 24, |crc, _| {
+            if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
@@ -0,0 +1,315 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::synth::SynthState;
+
+/// What a parameter accepts. Serialized for the UI as `{"type": ..., ...}`.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ParamKind {
+    Number { min: f64, max: f64 },
+    Integer { min: u64, max: u64, step: u64 },
+    Bool,
+    Choice { options: &'static [&'static str] },
+}
+
+/// One addressable field of [`SynthState`].
+pub struct Parameter {
+    /// Dotted path used by automation events, e.g. `"filter.cutoff"`.
+    pub path: &'static str,
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub kind: ParamKind,
+    pub automatable: bool,
+    get: fn(&SynthState) -> Value,
+    /// Stores the value if it has the right JSON type; range is not checked.
+    set: fn(&mut SynthState, &Value) -> bool,
+}
+
+/// Why a value was refused by [`Parameter::check`].
+#[derive(Debug, Clone)]
+pub enum ValueError {
+    TypeMismatch(String),
+    OutOfRange(String),
+}
+
+impl ValueError {
+    pub fn message(&self) -> &str {
+        match self {
+            Self::TypeMismatch(message) | Self::OutOfRange(message) => message,
+        }
+    }
+}
+
+impl Parameter {
+    const fn fixed(self) -> Self {
+        Self {
+            automatable: false,
+            ..self
+        }
+    }
+
+    pub fn get(&self, state: &SynthState) -> Value {
+        (self.get)(state)
+    }
+
+    pub fn check(&self, value: &Value) -> Result<(), ValueError> {
+        let mismatch = |expected: &str| {
+            Err(ValueError::TypeMismatch(format!(
+                "expected {expected}, got {value}"
+            )))
+        };
+        match self.kind {
+            ParamKind::Number { min, max } => match value.as_f64() {
+                None => mismatch("a number"),
+                Some(v) if !(min..=max).contains(&v) => Err(ValueError::OutOfRange(format!(
+                    "{v} is outside {min}..{max}"
+                ))),
+                Some(_) => Ok(()),
+            },
+            ParamKind::Integer { min, max, step } => match value.as_u64() {
+                None => mismatch("a whole number"),
+                Some(v) if !(min..=max).contains(&v) || (v - min) % step != 0 => {
+                    Err(ValueError::OutOfRange(format!(
+                        "{v} is not one of {}",
+                        self.kind.describe_values()
+                    )))
+                }
+                Some(_) => Ok(()),
+            },
+            ParamKind::Bool => match value.is_boolean() {
+                false => mismatch("a boolean"),
+                true => Ok(()),
+            },
+            ParamKind::Choice { options } => match value.as_str() {
+                None => mismatch("a string"),
+                Some(v) if !options.contains(&v) => Err(ValueError::OutOfRange(format!(
+                    "{v:?} is not one of {}",
+                    options.join("|")
+                ))),
+                Some(_) => Ok(()),
+            },
+        }
+    }
+}
+
+impl ParamKind {
+    /// Short human description of the accepted values, e.g. `12|24`.
+    fn describe_values(&self) -> String {
+        match *self {
+            Self::Number { min, max } => format!("{min}..{max}"),
+            Self::Integer { min, max, step } if (max - min) / step < 8 => (min..=max)
+                .step_by(step as usize)
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join("|"),
+            Self::Integer { min, max, .. } => format!("{min}..{max}"),
+            Self::Bool => "true|false".into(),
+            Self::Choice { options } => options.join("|"),
+        }
+    }
+}
+
+macro_rules! number {
+    ($path:literal, $name:literal, $unit:literal, $min:expr, $max:expr, $($field:ident).+) => {
+        Parameter {
+            path: $path,
+            name: $name,
+            unit: $unit,
+            kind: ParamKind::Number { min: $min, max: $max },
+            automatable: true,
+            get: |s| Value::from(s.$($field).+),
+            set: |s, v| v.as_f64().map(|v| s.$($field).+ = v as f32).is_some(),
+        }
+    };
+}
+
+macro_rules! integer {
+    ($path:literal, $name:literal, $unit:literal, $min:expr, $max:expr, $step:expr, $ty:ty, $($field:ident).+) => {
+        Parameter {
+            path: $path,
+            name: $name,
+            unit: $unit,
+            kind: ParamKind::Integer { min: $min, max: $max, step: $step },
+            automatable: true,
+            get: |s| Value::from(s.$($field).+),
+            set: |s, v| v.as_u64().map(|v| s.$($field).+ = v as $ty).is_some(),
+        }
+    };
+}
+
+macro_rules! boolean {
+    ($path:literal, $name:literal, $($field:ident).+) => {
+        Parameter {
+            path: $path,
+            name: $name,
+            unit: "",
+            kind: ParamKind::Bool,
+            automatable: true,
+            get: |s| Value::from(s.$($field).+),
+            set: |s, v| v.as_bool().map(|v| s.$($field).+ = v).is_some(),
+        }
+    };
+}
+
+macro_rules! choice {
+    ($path:literal, $name:literal, $options:expr, $($field:ident).+) => {
+        Parameter {
+            path: $path,
+            name: $name,
+            unit: "",
+            kind: ParamKind::Choice { options: $options },
+            automatable: true,
+            get: |s| Value::from(s.$($field).+.clone()),
+            set: |s, v| {
+                v.as_str()
+                    .map(|v| {
+                        // Reuse the existing buffer; see `reserve_choices`.
+                        s.$($field).+.clear();
+                        s.$($field).+.push_str(v);
+                    })
+                    .is_some()
+            },
+        }
+    };
+}
+
+const WAVEFORMS: &[&str] = &["sine", "triangle", "saw", "square"];
+
+/// Every field of [`SynthState`], in the order the UI lays them out.
+static PARAMETERS: &[Parameter] = &[
+    number!("envelope.attack", "Attack", "s", 0.0, 10.0, envelope.attack),
+    number!("envelope.decay", "Decay", "s", 0.0, 10.0, envelope.decay),
+    number!("envelope.sustain", "Sustain", "", 0.0, 1.0, envelope.sustain),
+    number!("envelope.release", "Release", "s", 0.0, 10.0, envelope.release),
+    choice!("oscillator.waveform", "Waveform", WAVEFORMS, oscillator.waveform),
+    number!("oscillator.tune", "Tune", "semitones", -48.0, 48.0, oscillator.tune),
+    number!("oscillator.level", "Level", "", 0.0, 1.0, oscillator.level),
+    boolean!("oscillator.sync", "Sync oscillator 2", oscillator.sync),
+    choice!("oscillator2.waveform", "Osc 2 waveform", WAVEFORMS, oscillator2.waveform),
+    number!("oscillator2.tune", "Osc 2 tune", "semitones", -48.0, 48.0, oscillator2.tune),
+    number!("oscillator2.level", "Osc 2 level", "", 0.0, 1.0, oscillator2.level),
+    number!("filter.cutoff", "Cutoff", "Hz", 20.0, 20000.0, filter.cutoff),
+    number!("filter.resonance", "Resonance", "", 0.0, 1.0, filter.resonance),
+    number!("filter.env_amount", "Env amount", "", -1.0, 1.0, filter.env_amount),
+    number!("filter.drive", "Drive", "", 0.0, 1.0, filter.drive),
+    choice!(
+        "filter.mode",
+        "Filter mode",
+        &["lowpass", "highpass", "bandpass", "notch"],
+        filter.mode
+    ),
+    integer!("filter.slope", "Slope", "dB/oct", 12, 24, 12, u8, filter.slope),
+    number!("mixer.noise", "Noise", "", 0.0, 1.0, mixer.noise),
+    number!("mixer.sub", "Sub", "", 0.0, 1.0, mixer.sub),
+    number!("mixer.master", "Master", "", 0.0, 1.0, mixer.master),
+    choice!("mixer.noise_color", "Noise color", &["white", "pink"], mixer.noise_color),
+    integer!("mixer.sub_octave", "Sub octave", "octaves", 1, 2, 1, u8, mixer.sub_octave),
+    number!("mixer.pan", "Pan", "", -1.0, 1.0, mixer.pan),
+    number!("mixer.width", "Stereo width", "", 0.0, 2.0, mixer.width),
+    number!("mixer.pan_spread", "Pan spread", "", 0.0, 1.0, mixer.pan_spread),
+    integer!("unison.voices", "Unison voices", "voices", 1, 7, 1, u8, unison.voices).fixed(),
+    number!("unison.detune", "Unison detune", "cents", 0.0, 100.0, unison.detune),
+    number!("unison.spread", "Unison spread", "", 0.0, 1.0, unison.spread),
+    boolean!("global.mono", "Mono", global.mono),
+    boolean!("global.legato", "Legato", global.legato),
+    number!("global.glide", "Glide", "", 0.0, 1.0, global.glide),
+    number!("global.clip_amount", "Clip", "", 0.05, 1.0, global.clip_amount),
+    integer!("global.polyphony", "Polyphony", "voices", 1, 16, 1, u32, global.polyphony).fixed(),
+    choice!(
+        "global.voice_steal",
+        "Voice stealing",
+        &["oldest", "quietest"],
+        global.voice_steal
+    )
+    .fixed(),
+    choice!("global.quality", "Quality", &["draft", "standard"], global.quality).fixed(),
+    number!("global.smoothing_ms", "Smoothing", "ms", 0.0, 1000.0, global.smoothing_ms).fixed(),
+];
+
+pub fn find(path: &str) -> Option<&'static Parameter> {
+    PARAMETERS.iter().find(|p| p.path == path)
+}
+
+impl SynthState {
+    pub fn get_path(&self, path: &str) -> Option<Value> {
+        find(path).map(|p| p.get(self))
+    }
+
+    /// Sets the field at `path`, refusing unknown paths and values of the
+    /// wrong type or outside the parameter's range.
+    pub fn set_path(&mut self, path: &str, value: &Value) -> Result<(), String> {
+        let parameter = find(path).ok_or_else(|| format!("unknown parameter path {path:?}"))?;
+        parameter
+            .check(value)
+            .map_err(|e| format!("{path}: {}", e.message()))?;
+        (parameter.set)(self, value);
+        Ok(())
+    }
+}
+
+/// Grows every choice field to fit its longest option, so switching choices
+/// afterwards (as live automation does on the audio thread) never allocates.
+pub fn reserve_choices(state: &mut SynthState) {
+    for p in PARAMETERS {
+        if let ParamKind::Choice { options } = p.kind {
+            let current = p.get(state);
+            if let Some(longest) = options.iter().max_by_key(|o| o.len()) {
+                (p.set)(state, &Value::from(*longest));
+            }
+            (p.set)(state, &current);
+        }
+    }
+}
+
+/// Registry entry as sent to the UI.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParameterInfo {
+    pub path: &'static str,
+    pub name: &'static str,
+    pub unit: &'static str,
+    #[serde(flatten)]
+    pub kind: ParamKind,
+    pub default: Value,
+    pub automatable: bool,
+}
+
+pub fn list() -> Vec<ParameterInfo> {
+    let defaults = SynthState::default();
+    PARAMETERS
+        .iter()
+        .map(|p| ParameterInfo {
+            path: p.path,
+            name: p.name,
+            unit: p.unit,
+            kind: p.kind,
+            default: p.get(&defaults),
+            automatable: p.automatable,
+        })
+        .collect()
+}
+
+/// One line per automatable path, in the format the AI system prompt uses.
+pub fn prompt_reference() -> String {
+    PARAMETERS
+        .iter()
+        .filter(|p| p.automatable)
+        .map(|p| {
+            let values = match p.kind {
+                ParamKind::Number { .. } => format!("number {}", p.kind.describe_values()),
+                ParamKind::Integer { .. } => {
+                    format!("whole number {}", p.kind.describe_values())
+                }
+                ParamKind::Bool => "boolean".into(),
+                ParamKind::Choice { .. } => format!("string: {}", p.kind.describe_values()),
+            };
+            let unit = if p.unit.is_empty() {
+                String::new()
+            } else {
+                format!(" {}", p.unit)
+            };
+            format!("- {} — {} ({values}{unit})", p.path, p.name)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -1,102 +1,686 @@
-use crate::automation::{apply_event, AutomationEvent};
-use crate::synth::SynthEngine;
+use crate::automation::{describe_issues, validate_events, Automation, AutomationEvent, Player};
+use crate::destination::{self, NameFields, Overwrite, DEFAULT_TEMPLATE};
+use crate::dither::{Dither, Quantizer};
+use crate::dsp::Engine;
+use crate::flac::FlacWriter;
+use crate::metadata::{self, Encoding, RenderMetadata, METADATA_VERSION};
+use crate::synth::{SynthEngine, SynthState};
+use crate::vorbis::VorbisWriter;
+use chrono::{DateTime, Local};
 use hound::{SampleFormat, WavSpec, WavWriter};
-use std::f32::consts::PI;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tauri::State;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct RenderRequest {
     pub duration_ms: u64,
     pub sample_rate: u32,
     pub events: Vec<AutomationEvent>,
+    /// MIDI note to render; defaults to A3 (220 Hz).
+    #[serde(default = "default_note")]
+    pub note: u8,
+    #[serde(default = "default_velocity")]
+    pub velocity: f32,
+    /// How long the note is held, in ms. Defaults to releasing early enough
+    /// for the release tail to finish inside `duration_ms`.
+    #[serde(default)]
+    pub gate_ms: Option<u64>,
+    /// 1 for a mono fold-down, 2 for stereo (the default).
+    #[serde(default = "default_channels")]
+    pub channels: u16,
+    /// Directory to write into. Defaults to "Andromeda Samples" on the
+    /// desktop, then in the music directory, then the app's data directory.
+    #[serde(default)]
+    pub output_dir: Option<String>,
+    /// File name without extension. Tokens: `{preset}`, `{date}`, `{time}`,
+    /// `{timestamp}`, `{sample_rate}`, `{note}`, `{duration_ms}`,
+    /// `{channels}`.
+    #[serde(default)]
+    pub filename_template: Option<String>,
+    /// Fills the `{preset}` token.
+    #[serde(default)]
+    pub preset_name: Option<String>,
+    #[serde(default)]
+    pub overwrite: Overwrite,
+    /// 16, 24 or 32 for integer samples; 32 for float.
+    #[serde(default = "default_bit_depth")]
+    pub bit_depth: u16,
+    #[serde(default)]
+    pub sample_format: SampleEncoding,
+    /// Only used for 16-bit output.
+    #[serde(default)]
+    pub dither: Dither,
+    #[serde(default)]
+    pub format: ExportFormat,
 }
 
-fn wave_value(waveform: &str, phase: f32) -> f32 {
-    match waveform {
-        "square" => {
-            if phase < 0.5 {
-                1.0
-            } else {
-                -1.0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Wav,
+    /// Lossless; 16 or 24-bit integer samples only.
+    Flac,
+    /// Lossy, encoded from float samples; `bit_depth`, `sample_format` and
+    /// `dither` don't apply.
+    OggVorbis,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::Flac => "flac",
+            Self::OggVorbis => "ogg",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleEncoding {
+    #[default]
+    Int,
+    Float,
+}
+
+fn default_note() -> u8 {
+    57
+}
+
+fn default_velocity() -> f32 {
+    1.0
+}
+
+fn default_channels() -> u16 {
+    2
+}
+
+fn default_bit_depth() -> u16 {
+    16
+}
+
+fn wav_spec(request: &RenderRequest) -> Result<WavSpec, String> {
+    if request.format == ExportFormat::OggVorbis {
+        return Ok(WavSpec {
+            channels: request.channels,
+            sample_rate: request.sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        });
+    }
+    let sample_format = match (request.sample_format, request.bit_depth) {
+        (SampleEncoding::Int, 16 | 24 | 32) => SampleFormat::Int,
+        (SampleEncoding::Float, 32) => SampleFormat::Float,
+        (SampleEncoding::Int, bits) => {
+            return Err(format!(
+                "unsupported bit depth {bits}; expected 16, 24 or 32"
+            ))
+        }
+        (SampleEncoding::Float, bits) => {
+            return Err(format!("float samples must be 32-bit, not {bits}"))
+        }
+    };
+    match request.format {
+        ExportFormat::Wav | ExportFormat::OggVorbis => {}
+        ExportFormat::Flac if sample_format == SampleFormat::Int && request.bit_depth <= 24 => {}
+        ExportFormat::Flac => {
+            return Err("FLAC export supports 16 or 24-bit integer samples".into())
+        }
+    }
+    Ok(WavSpec {
+        channels: request.channels,
+        sample_rate: request.sample_rate,
+        bits_per_sample: request.bit_depth,
+        sample_format,
+    })
+}
+
+/// The encoder a render writes through.
+enum SampleWriter {
+    Wav(WavWriter<BufWriter<File>>),
+    Flac(FlacWriter<BufWriter<File>>),
+    Vorbis(Box<VorbisWriter<BufWriter<File>>>),
+}
+
+impl SampleWriter {
+    fn new(
+        file: File,
+        spec: WavSpec,
+        format: ExportFormat,
+        tags: &[(&str, String)],
+    ) -> Result<Self, String> {
+        let out = BufWriter::new(file);
+        match format {
+            ExportFormat::Flac => FlacWriter::new(
+                out,
+                spec.sample_rate,
+                spec.channels,
+                spec.bits_per_sample,
+                tags,
+            )
+            .map(Self::Flac)
+            .map_err(|e| format!("flac writer error: {e}")),
+            ExportFormat::OggVorbis => {
+                VorbisWriter::new(out, spec.sample_rate, spec.channels, tags)
+                    .map(|writer| Self::Vorbis(Box::new(writer)))
+                    .map_err(|e| format!("vorbis writer error: {e}"))
             }
+            ExportFormat::Wav => WavWriter::new(out, spec)
+                .map(Self::Wav)
+                .map_err(|e| format!("wav writer error: {e}")),
+        }
+    }
+
+    fn write_float(&mut self, sample: f32) -> Result<(), String> {
+        match self {
+            Self::Wav(writer) => writer
+                .write_sample(sample)
+                .map_err(|e| format!("wav write error: {e}")),
+            Self::Flac(_) => Err("FLAC can't store float samples".into()),
+            Self::Vorbis(writer) => writer
+                .write_sample(sample)
+                .map_err(|e| format!("vorbis write error: {e}")),
+        }
+    }
+
+    fn write_int(&mut self, sample: i32) -> Result<(), String> {
+        match self {
+            Self::Wav(writer) => writer
+                .write_sample(sample)
+                .map_err(|e| format!("wav write error: {e}")),
+            Self::Flac(writer) => writer
+                .write_sample(sample)
+                .map_err(|e| format!("flac write error: {e}")),
+            Self::Vorbis(_) => Err("Vorbis is encoded from float samples".into()),
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            Self::Wav(writer) => writer
+                .finalize()
+                .map_err(|e| format!("wav finalize error: {e}")),
+            Self::Flac(writer) => writer
+                .finish()
+                .map(drop)
+                .map_err(|e| format!("flac finalize error: {e}")),
+            Self::Vorbis(writer) => (*writer)
+                .finish()
+                .map(drop)
+                .map_err(|e| format!("vorbis finalize error: {e}")),
         }
-        "saw" => 2.0 * phase - 1.0,
-        "triangle" => 1.0 - 4.0 * (phase - 0.5).abs(),
-        _ => (phase * 2.0 * PI).sin(),
     }
 }
 
-fn soft_clip(x: f32) -> f32 {
-    x / (1.0 + x.abs())
+/// Samples rendered per engine call between automation events.
+const RENDER_BLOCK: usize = 1024;
+const PROGRESS_EVENT: &str = "render://progress";
+const FINISHED_EVENT: &str = "render://finished";
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Renders running on worker threads.
+#[derive(Default)]
+pub struct RenderJobs {
+    next_id: AtomicU64,
+    /// Cancel flags of the jobs still running.
+    running: Arc<Mutex<HashMap<u64, Arc<AtomicBool>>>>,
+}
+
+/// Returned by `render_sample` as soon as the job starts.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RenderStarted {
+    pub job_id: u64,
+    /// Where the file is being written.
+    pub path: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RenderProgress {
+    pub job_id: u64,
+    pub percent: f64,
+    pub elapsed_ms: u64,
+    /// `None` until there is a rate to estimate from.
+    pub eta_ms: Option<u64>,
+}
+
+impl RenderProgress {
+    fn new(job_id: u64, done: u64, total: u64, elapsed: Duration) -> Self {
+        let elapsed_ms = elapsed.as_millis() as u64;
+        Self {
+            job_id,
+            percent: if total == 0 {
+                100.0
+            } else {
+                done as f64 * 100.0 / total as f64
+            },
+            elapsed_ms,
+            eta_ms: (done > 0)
+                .then(|| (elapsed_ms as f64 * (total - done) as f64 / done as f64) as u64),
+        }
+    }
+}
+
+/// Emitted once per job when it ends.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RenderFinished {
+    Done {
+        job_id: u64,
+        path: String,
+    },
+    Failed {
+        job_id: u64,
+        error: String,
+    },
+    /// The partial file has been deleted.
+    Cancelled {
+        job_id: u64,
+    },
+}
+
+/// A render set up by `render_sample`, run on the worker thread.
+struct RenderJob {
+    request: RenderRequest,
+    spec: WavSpec,
+    state: SynthState,
+    writer: SampleWriter,
+    path: PathBuf,
+    created: DateTime<Local>,
+}
+
+impl RenderJob {
+    /// Returns `Ok(false)` if `cancel` was set before the render finished.
+    /// `progress` gets the samples done and the total after every block.
+    /// The file is deleted unless the render finished.
+    fn run(self, cancel: &AtomicBool, progress: impl FnMut(u64, u64)) -> Result<bool, String> {
+        let path = self.path.clone();
+        let result = self.render(cancel, progress);
+        if result != Ok(true) {
+            let _ = std::fs::remove_file(&path);
+        }
+        result
+    }
+
+    fn render(
+        self,
+        cancel: &AtomicBool,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<bool, String> {
+        let Self {
+            request,
+            spec,
+            mut state,
+            mut writer,
+            path,
+            created,
+        } = self;
+        let mut player = Player::new(Automation::new(&request.events, request.sample_rate), None);
+        let total_samples =
+            (request.duration_ms as f64 * request.sample_rate as f64 / 1000.0) as u64;
+
+        // Renders shorter than the release still hold the note for half of
+        // their length rather than releasing it straight away.
+        let release_ms = (state.envelope.release.max(0.0) * 1000.0) as u64;
+        let gate_ms = request.gate_ms.unwrap_or_else(|| {
+            request
+                .duration_ms
+                .saturating_sub(release_ms)
+                .max(request.duration_ms / 2)
+        });
+        let gate_off_sample = (gate_ms as f64 * request.sample_rate as f64 / 1000.0) as u64;
+        let patch = RenderMetadata {
+            version: METADATA_VERSION,
+            preset_name: request.preset_name.clone(),
+            state: state.clone(),
+            events: request.events.clone(),
+            note: request.note,
+            velocity: request.velocity,
+            duration_ms: request.duration_ms,
+            gate_ms,
+            sample_rate: request.sample_rate,
+        };
+
+        let mut engine = Engine::new(request.sample_rate as f32, &state);
+        engine.note_on(request.note, request.velocity);
+        let mut quantizers =
+            [0, 1].map(|channel| Quantizer::new(spec.bits_per_sample, request.dither, channel + 1));
+        let mut left = vec![0.0f32; RENDER_BLOCK];
+        let mut right = vec![0.0f32; RENDER_BLOCK];
+        let mut position = 0u64;
+
+        while position < total_samples {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+            if !player.is_empty() {
+                player.apply(&mut state);
+                engine.set_state(&state);
+            }
+            if position == gate_off_sample {
+                engine.note_off(request.note);
+            }
+
+            let next_gate = if gate_off_sample > position {
+                gate_off_sample
+            } else {
+                u64::MAX
+            };
+            let len = player.block_len(
+                (total_samples - position)
+                    .min(next_gate - position)
+                    .min(RENDER_BLOCK as u64),
+            ) as usize;
+            engine.process(&mut left[..len], &mut right[..len]);
+            for (l, r) in left[..len].iter().zip(&right[..len]) {
+                let frame = if request.channels == 1 {
+                    [(l + r) * 0.5, 0.0]
+                } else {
+                    [*l, *r]
+                };
+                for (sample, quantizer) in
+                    frame.iter().zip(&mut quantizers).take(spec.channels.into())
+                {
+                    match spec.sample_format {
+                        SampleFormat::Float => writer.write_float(*sample)?,
+                        SampleFormat::Int => writer.write_int(quantizer.quantize(*sample))?,
+                    }
+                }
+            }
+            position += len as u64;
+            player.advance(len as u64);
+            progress(position, total_samples);
+        }
+
+        writer.finish()?;
+        if request.format == ExportFormat::Wav {
+            let encoding = Encoding {
+                bits: spec.bits_per_sample,
+                channels: spec.channels,
+            };
+            metadata::embed(&path, &patch, &encoding, created)?;
+        }
+        Ok(true)
+    }
 }
 
+/// Starts rendering on a worker thread and returns straight away. Progress
+/// is emitted as `render://progress` events and the result as one
+/// `render://finished` event. Invalid requests fail here, before a file is
+/// created.
 #[tauri::command]
-pub fn render_sample(request: RenderRequest, synth: State<SynthEngine>) -> Result<String, String> {
-    let mut events = request.events.clone();
-    events.sort_by_key(|e| e.time_ms);
+pub fn render_sample(
+    request: RenderRequest,
+    app: AppHandle,
+    synth: State<SynthEngine>,
+    jobs: State<RenderJobs>,
+) -> Result<RenderStarted, String> {
+    if !(1..=2).contains(&request.channels) {
+        return Err(format!("unsupported channel count {}", request.channels));
+    }
+    let spec = wav_spec(&request)?;
+    let issues = validate_events(&request.events, request.duration_ms);
+    if !issues.is_empty() {
+        return Err(describe_issues(&issues));
+    }
 
-    let mut state = synth
+    let state = synth
         .state
         .lock()
         .map(|guard| guard.clone())
         .map_err(|_| "synth state lock poisoned".to_string())?;
 
-    let total_samples = (request.duration_ms as f64 * request.sample_rate as f64 / 1000.0) as u64;
-    let spec = WavSpec {
-        channels: 1,
-        sample_rate: request.sample_rate,
-        bits_per_sample: 16,
-        sample_format: SampleFormat::Int,
+    let preset = request.preset_name.as_deref().unwrap_or("untitled");
+    let dir = destination::output_dir(request.output_dir.as_deref())?;
+    let stem = destination::file_stem(
+        request
+            .filename_template
+            .as_deref()
+            .unwrap_or(DEFAULT_TEMPLATE),
+        &NameFields {
+            preset,
+            sample_rate: request.sample_rate,
+            note: request.note,
+            duration_ms: request.duration_ms,
+            channels: request.channels,
+        },
+    )?;
+    let (file, path) =
+        destination::create(&dir, &stem, request.format.extension(), request.overwrite)?;
+    let created = Local::now();
+    let tags = [
+        ("TITLE", preset.to_string()),
+        ("DATE", created.format("%Y-%m-%d").to_string()),
+        (
+            "COMMENT",
+            format!("note {}, {} ms", request.note, request.duration_ms),
+        ),
+    ];
+    let writer = match SampleWriter::new(file, spec, request.format, &tags) {
+        Ok(writer) => writer,
+        Err(e) => {
+            let _ = std::fs::remove_file(&path);
+            return Err(e);
+        }
     };
 
-    let mut path = dirs::desktop_dir().ok_or("desktop directory not found")?;
-    path.push("Andromeda Samples");
-    std::fs::create_dir_all(&path)
-        .map_err(|e| format!("failed to create output directory: {e}"))?;
-    let millis = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|_| "system time before unix epoch".to_string())?
-        .as_millis();
-    let filename = format!("andromeda-render-{millis}.wav");
-    path.push(filename);
-
-    let mut writer = WavWriter::create(&path, spec)
-        .map_err(|e| format!("wav writer error: {e}"))?;
-
-    let mut phase = 0.0f32;
-    let mut z = 0.0f32;
-    let mut event_index = 0usize;
-
-    for i in 0..total_samples {
-        let t_ms = (i as f64 * 1000.0 / request.sample_rate as f64) as u64;
-        while event_index < events.len() && events[event_index].time_ms <= t_ms {
-            apply_event(&mut state, &events[event_index]);
-            event_index += 1;
-        }
-
-        let freq = 220.0 * 2.0f32.powf(state.oscillator.tune / 12.0);
-        let waveform = state.oscillator.waveform.as_str();
-        let level = state.oscillator.level.max(0.0).min(1.0);
-        let master = state.mixer.master.max(0.0).min(1.0);
-        let cutoff = state.filter.cutoff.max(20.0).min(20000.0);
-        let resonance = state.filter.resonance.max(0.0).min(1.0);
-        let clip_amount = state.global.clip_amount.max(0.05).min(1.0);
-
-        phase = (phase + freq / request.sample_rate as f32) % 1.0;
-        let raw = wave_value(waveform, phase) * level;
-        let a = (-2.0 * PI * cutoff / request.sample_rate as f32).exp();
-        let feedback = (1.0 + resonance * 3.0).min(3.5);
-        let input = raw - z * (feedback - 1.0);
-        z = (1.0 - a) * input + a * z;
-        let sample = soft_clip(z * master * clip_amount);
-        let out = (sample * i16::MAX as f32) as i16;
-        writer
-            .write_sample(out)
-            .map_err(|e| format!("wav write error: {e}"))?;
-    }
-
-    writer.finalize().map_err(|e| format!("wav finalize error: {e}"))?;
-
-    Ok(path.to_string_lossy().to_string())
+    let job_id = jobs.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let running = jobs.running.clone();
+    running
+        .lock()
+        .map_err(|_| "render jobs lock poisoned".to_string())?
+        .insert(job_id, cancel.clone());
+
+    let started = RenderStarted {
+        job_id,
+        path: path.to_string_lossy().to_string(),
+    };
+    let job = RenderJob {
+        request,
+        spec,
+        state,
+        writer,
+        path: path.clone(),
+        created,
+    };
+    std::thread::spawn(move || {
+        let start = Instant::now();
+        let mut last_report: Option<Instant> = None;
+        let result = job.run(&cancel, |done, total| {
+            if done < total && last_report.is_some_and(|at| at.elapsed() < PROGRESS_INTERVAL) {
+                return;
+            }
+            last_report = Some(Instant::now());
+            let _ = app.emit(
+                PROGRESS_EVENT,
+                RenderProgress::new(job_id, done, total, start.elapsed()),
+            );
+        });
+        let finished = match result {
+            Ok(true) => RenderFinished::Done {
+                job_id,
+                path: path.to_string_lossy().to_string(),
+            },
+            Ok(false) => RenderFinished::Cancelled { job_id },
+            Err(error) => RenderFinished::Failed { job_id, error },
+        };
+        if let Ok(mut running) = running.lock() {
+            running.remove(&job_id);
+        }
+        let _ = app.emit(FINISHED_EVENT, finished);
+    });
+    Ok(started)
+}
+
+/// Asks a running render to stop. The worker deletes the partial file and
+/// then emits `render://finished` with status `cancelled`. Returns `false`
+/// if the job already finished or never existed.
+#[tauri::command]
+pub fn render_cancel(job_id: u64, jobs: State<RenderJobs>) -> Result<bool, String> {
+    let running = jobs
+        .running
+        .lock()
+        .map_err(|_| "render jobs lock poisoned".to_string())?;
+    Ok(match running.get(&job_id) {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Reads back the patch, automation and note a WAV was rendered with.
+#[tauri::command]
+pub fn render_read_metadata(path: String) -> Result<RenderMetadata, String> {
+    metadata::read(Path::new(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::WavReader;
+
+    fn job(path: &Path, request: serde_json::Value) -> RenderJob {
+        let request: RenderRequest = serde_json::from_value(request).unwrap();
+        let spec = wav_spec(&request).unwrap();
+        let file = File::create(path).unwrap();
+        RenderJob {
+            writer: SampleWriter::new(file, spec, request.format, &[]).unwrap(),
+            request,
+            spec,
+            state: SynthState::default(),
+            path: path.to_path_buf(),
+            created: Local::now(),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("andromeda-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn renders_shorter_than_the_release_still_sound() {
+        let dir = temp_dir("short-render");
+        let path = dir.join("short.wav");
+        let request = serde_json::json!({
+            "duration_ms": 300,
+            "sample_rate": 44_100,
+            "events": [],
+            "sample_format": "float",
+            "bit_depth": 32,
+        });
+        let finished = job(&path, request).run(&AtomicBool::new(false), |_, _| {});
+        assert_eq!(finished, Ok(true));
+
+        let peak = WavReader::open(&path)
+            .unwrap()
+            .into_samples::<f32>()
+            .map(|s| s.unwrap().abs())
+            .fold(0.0, f32::max);
+        assert!(peak > 0.1, "peak {peak}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn huge_gates_hold_the_note_to_the_end() {
+        let dir = temp_dir("long-gate");
+        let path = dir.join("gate.wav");
+        let request = serde_json::json!({
+            "duration_ms": 50,
+            "sample_rate": 44_100,
+            "events": [],
+            "gate_ms": u64::MAX / 1000,
+        });
+        let finished = job(&path, request).run(&AtomicBool::new(false), |_, _| {});
+        assert_eq!(finished, Ok(true));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn renders_ogg_vorbis() {
+        let dir = temp_dir("vorbis");
+        let path = dir.join("render.ogg");
+        let request = serde_json::json!({
+            "duration_ms": 250,
+            "sample_rate": 48_000,
+            "events": [],
+            "format": "ogg_vorbis",
+        });
+        let finished = job(&path, request).run(&AtomicBool::new(false), |_, _| {});
+        assert_eq!(finished, Ok(true));
+
+        let mut reader = lewton::inside_ogg::OggStreamReader::new(File::open(&path).unwrap())
+            .expect("decodable stream");
+        assert_eq!(reader.ident_hdr.audio_channels, 2);
+        let (mut frames, mut peak) = (0, 0.0f32);
+        while let Some(packet) = reader.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap() {
+            frames += packet[0].len();
+            peak = packet
+                .iter()
+                .flatten()
+                .fold(peak, |peak, s| peak.max(s.abs()));
+        }
+        assert_eq!(frames, 12_000);
+        assert!(peak > 0.1, "peak {peak}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cancelling_stops_the_render_and_deletes_the_file() {
+        let dir = temp_dir("cancel");
+        let path = dir.join("cancel.wav");
+        let request = serde_json::json!({
+            "duration_ms": 2000,
+            "sample_rate": 44_100,
+            "events": [],
+        });
+        let cancel = AtomicBool::new(false);
+        let mut reports = 0;
+        let finished = job(&path, request).run(&cancel, |_, _| {
+            reports += 1;
+            cancel.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(finished, Ok(false));
+        assert_eq!(reports, 1, "kept rendering after the cancel");
+        assert!(!path.exists(), "partial file left behind");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn progress_rises_to_completion() {
+        let dir = temp_dir("progress");
+        let path = dir.join("progress.wav");
+        let request = serde_json::json!({
+            "duration_ms": 500,
+            "sample_rate": 44_100,
+            "events": [
+                { "time_ms": 100.0, "path": "filter.cutoff", "value": 400.0 },
+                { "time_ms": 400.0, "path": "filter.cutoff", "value": 4000.0, "curve": "linear" },
+            ],
+        });
+        let mut reports = Vec::new();
+        let finished = job(&path, request).run(&AtomicBool::new(false), |done, total| {
+            reports.push((done, total));
+        });
+        assert_eq!(finished, Ok(true));
+
+        let total = 22_050;
+        assert!(reports.iter().all(|&(_, t)| t == total));
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(reports.last(), Some(&(total, total)));
+        let last = RenderProgress::new(1, total, total, Duration::from_secs(1));
+        assert_eq!(last.percent, 100.0);
+        assert_eq!(last.eta_ms, Some(0));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
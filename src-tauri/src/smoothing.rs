@@ -0,0 +1,133 @@
+use crate::dsp::Params;
+
+/// One-pole glide from the current value towards a target.
+#[derive(Debug, Clone, Copy)]
+pub struct Smoother {
+    current: f32,
+    target: f32,
+}
+
+impl Smoother {
+    pub fn new(value: f32) -> Self {
+        Self {
+            current: value,
+            target: value,
+        }
+    }
+
+    pub fn set(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    pub fn next(&mut self, coeff: f32) -> f32 {
+        self.current = self.target + (self.current - self.target) * coeff;
+        // Land exactly so settled parameters stop costing denormals.
+        if (self.current - self.target).abs() < 1.0e-6 {
+            self.current = self.target;
+        }
+        self.current
+    }
+}
+
+/// Per-sample coefficient for a time constant of `time` seconds; zero jumps.
+pub fn coefficient(time: f32, sample_rate: f32) -> f32 {
+    if time <= 0.0 {
+        0.0
+    } else {
+        (-1.0 / (time * sample_rate)).exp()
+    }
+}
+
+/// Smoothers for every continuous parameter in [`Params`]. Discrete settings
+/// (waveforms, modes, flags) and envelope/glide times switch immediately;
+/// they either can't be interpolated or don't click when they jump.
+#[derive(Debug, Clone, Copy)]
+pub struct ParamSmoother {
+    tune: Smoother,
+    level: Smoother,
+    tune2: Smoother,
+    level2: Smoother,
+    noise: Smoother,
+    sub: Smoother,
+    /// Smoothed in octaves so sweeps sound even across the range.
+    cutoff_octaves: Smoother,
+    resonance: Smoother,
+    env_amount: Smoother,
+    drive: Smoother,
+    master: Smoother,
+    pan: Smoother,
+    width: Smoother,
+    pan_spread: Smoother,
+    unison_detune: Smoother,
+    unison_spread: Smoother,
+    clip_amount: Smoother,
+}
+
+impl ParamSmoother {
+    pub fn new(p: &Params) -> Self {
+        Self {
+            tune: Smoother::new(p.tune),
+            level: Smoother::new(p.level),
+            tune2: Smoother::new(p.tune2),
+            level2: Smoother::new(p.level2),
+            noise: Smoother::new(p.noise),
+            sub: Smoother::new(p.sub),
+            cutoff_octaves: Smoother::new(p.cutoff.log2()),
+            resonance: Smoother::new(p.resonance),
+            env_amount: Smoother::new(p.env_amount),
+            drive: Smoother::new(p.drive),
+            master: Smoother::new(p.master),
+            pan: Smoother::new(p.pan),
+            width: Smoother::new(p.width),
+            pan_spread: Smoother::new(p.pan_spread),
+            unison_detune: Smoother::new(p.unison_detune),
+            unison_spread: Smoother::new(p.unison_spread),
+            clip_amount: Smoother::new(p.clip_amount),
+        }
+    }
+
+    pub fn set(&mut self, p: &Params) {
+        self.tune.set(p.tune);
+        self.level.set(p.level);
+        self.tune2.set(p.tune2);
+        self.level2.set(p.level2);
+        self.noise.set(p.noise);
+        self.sub.set(p.sub);
+        self.cutoff_octaves.set(p.cutoff.log2());
+        self.resonance.set(p.resonance);
+        self.env_amount.set(p.env_amount);
+        self.drive.set(p.drive);
+        self.master.set(p.master);
+        self.pan.set(p.pan);
+        self.width.set(p.width);
+        self.pan_spread.set(p.pan_spread);
+        self.unison_detune.set(p.unison_detune);
+        self.unison_spread.set(p.unison_spread);
+        self.clip_amount.set(p.clip_amount);
+    }
+
+    /// Advances one sample and returns `target` with its continuous fields
+    /// replaced by their smoothed values.
+    pub fn next(&mut self, target: &Params, coeff: f32) -> Params {
+        Params {
+            tune: self.tune.next(coeff),
+            level: self.level.next(coeff),
+            tune2: self.tune2.next(coeff),
+            level2: self.level2.next(coeff),
+            noise: self.noise.next(coeff),
+            sub: self.sub.next(coeff),
+            cutoff: self.cutoff_octaves.next(coeff).exp2(),
+            resonance: self.resonance.next(coeff),
+            env_amount: self.env_amount.next(coeff),
+            drive: self.drive.next(coeff),
+            master: self.master.next(coeff),
+            pan: self.pan.next(coeff),
+            width: self.width.next(coeff),
+            pan_spread: self.pan_spread.next(coeff),
+            unison_detune: self.unison_detune.next(coeff),
+            unison_spread: self.unison_spread.next(coeff),
+            clip_amount: self.clip_amount.next(coeff),
+            ..*target
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+
+use crate::dsp::Params;
+
+/// Most unison copies a voice can stack.
+pub const MAX_UNISON: usize = 7;
+
+/// Left/right gains for `pan` in `-1.0..=1.0`. Equal-power, scaled so the
+/// centre is unity gain and a centred voice sounds as it did in mono.
+pub fn pan_gains(pan: f32) -> (f32, f32) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+    (angle.cos() * SQRT_2, angle.sin() * SQRT_2)
+}
+
+/// Mid/side width: 0 folds to mono, 1 leaves the signal alone, 2 doubles
+/// the side signal.
+pub fn widen(left: f32, right: f32, width: f32) -> (f32, f32) {
+    let mid = (left + right) * 0.5;
+    let side = (left - right) * 0.5 * width;
+    (mid + side, mid - side)
+}
+
+/// Pitch ratio and stereo gains for each unison copy. The same for every
+/// voice, so the engine works it out once per sample.
+#[derive(Debug, Clone, Copy)]
+pub struct UnisonLayout {
+    pub count: usize,
+    pub ratios: [f32; MAX_UNISON],
+    pub gains: [(f32, f32); MAX_UNISON],
+    /// Gains for the parts of a voice that aren't stacked (sub and noise).
+    pub center: (f32, f32),
+    /// Keeps the stack at roughly the level of a single copy.
+    pub normalize: f32,
+}
+
+impl UnisonLayout {
+    pub fn new(p: &Params) -> Self {
+        let mut layout = Self {
+            count: p.unison,
+            ratios: [1.0; MAX_UNISON],
+            gains: [(1.0, 1.0); MAX_UNISON],
+            center: pan_gains(p.pan),
+            normalize: 1.0 / (p.unison as f32).sqrt(),
+        };
+        for i in 0..p.unison {
+            // Copies sit evenly from -1 to 1; a single copy sits at 0.
+            let offset = if p.unison > 1 {
+                2.0 * i as f32 / (p.unison - 1) as f32 - 1.0
+            } else {
+                0.0
+            };
+            layout.ratios[i] = (offset * p.unison_detune / 1200.0).exp2();
+            layout.gains[i] = pan_gains(p.pan + offset * p.unison_spread);
+        }
+        layout
+    }
+}
@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::sync::{Arc, Mutex};
 use tauri::State;
+use triple_buffer::{Input, Output, TripleBuffer};
+
+use crate::params::{self, ParameterInfo};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Envelope {
@@ -18,12 +22,44 @@ pub struct Oscillator {
     pub sync: bool,
 }
 
+/// Second oscillator; hard-synced to the first when `Oscillator.sync` is on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Oscillator2 {
+    pub waveform: String,
+    pub tune: f32,
+    pub level: f32,
+}
+
+impl Default for Oscillator2 {
+    fn default() -> Self {
+        Self {
+            waveform: "saw".into(),
+            tune: 12.0,
+            level: 0.0,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Filter {
     pub cutoff: f32,
     pub resonance: f32,
     pub env_amount: f32,
     pub drive: f32,
+    /// `"lowpass"`, `"highpass"`, `"bandpass"` or `"notch"`.
+    #[serde(default = "default_filter_mode")]
+    pub mode: String,
+    /// Rolloff in dB/octave: 12 or 24.
+    #[serde(default = "default_filter_slope")]
+    pub slope: u8,
+}
+
+fn default_filter_mode() -> String {
+    "lowpass".into()
+}
+
+fn default_filter_slope() -> u8 {
+    12
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +67,55 @@ pub struct Mixer {
     pub noise: f32,
     pub sub: f32,
     pub master: f32,
+    /// `"white"` or `"pink"`.
+    #[serde(default = "default_noise_color")]
+    pub noise_color: String,
+    /// How far below the oscillator the sub sits: 1 or 2 octaves.
+    #[serde(default = "default_sub_octave")]
+    pub sub_octave: u8,
+    /// Voice position from -1 (left) to 1 (right).
+    #[serde(default)]
+    pub pan: f32,
+    /// Stereo width of the output: 0 is mono, 1 unchanged, 2 widened.
+    #[serde(default = "default_width")]
+    pub width: f32,
+    /// Spreads voices across the stereo field by pitch, low notes to the
+    /// left and high notes to the right: 0 plays every voice at `pan`.
+    #[serde(default)]
+    pub pan_spread: f32,
+}
+
+fn default_noise_color() -> String {
+    "white".into()
+}
+
+fn default_sub_octave() -> u8 {
+    1
+}
+
+fn default_width() -> f32 {
+    1.0
+}
+
+/// Stacked, detuned copies of both oscillators within each voice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unison {
+    /// Copies per voice; 1 disables unison.
+    pub voices: u8,
+    /// Detune of the outermost copies, in cents either side.
+    pub detune: f32,
+    /// How far the copies fan out across the stereo field, 0 to 1.
+    pub spread: f32,
+}
+
+impl Default for Unison {
+    fn default() -> Self {
+        Self {
+            voices: 1,
+            detune: 20.0,
+            spread: 0.5,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,14 +123,48 @@ pub struct Global {
     pub mono: bool,
     pub glide: f32,
     pub clip_amount: f32,
+    /// In mono mode, only glide between overlapping notes.
+    #[serde(default)]
+    pub legato: bool,
+    #[serde(default = "default_polyphony")]
+    pub polyphony: u32,
+    /// `"oldest"` or `"quietest"`.
+    #[serde(default = "default_voice_steal")]
+    pub voice_steal: String,
+    /// Oscillator anti-aliasing: `"draft"` (naive) or `"standard"` (PolyBLEP).
+    #[serde(default = "default_quality")]
+    pub quality: String,
+    /// Time constant for continuous parameter changes; 0 disables smoothing.
+    #[serde(default = "default_smoothing_ms")]
+    pub smoothing_ms: f32,
+}
+
+fn default_polyphony() -> u32 {
+    8
+}
+
+fn default_voice_steal() -> String {
+    "oldest".into()
+}
+
+fn default_quality() -> String {
+    "standard".into()
+}
+
+fn default_smoothing_ms() -> f32 {
+    10.0
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SynthState {
     pub envelope: Envelope,
     pub oscillator: Oscillator,
+    #[serde(default)]
+    pub oscillator2: Oscillator2,
     pub filter: Filter,
     pub mixer: Mixer,
+    #[serde(default)]
+    pub unison: Unison,
     pub global: Global,
 }
 
@@ -64,21 +183,35 @@ impl Default for SynthState {
                 level: 0.7,
                 sync: true,
             },
+            oscillator2: Oscillator2::default(),
             filter: Filter {
                 cutoff: 1400.0,
                 resonance: 0.35,
                 env_amount: 0.55,
                 drive: 0.2,
+                mode: default_filter_mode(),
+                slope: default_filter_slope(),
             },
             mixer: Mixer {
                 noise: 0.12,
                 sub: 0.3,
                 master: 0.72,
+                noise_color: default_noise_color(),
+                sub_octave: default_sub_octave(),
+                pan: 0.0,
+                width: default_width(),
+                pan_spread: 0.0,
             },
+            unison: Unison::default(),
             global: Global {
                 mono: false,
                 glide: 0.05,
                 clip_amount: 0.35,
+                legato: false,
+                polyphony: default_polyphony(),
+                voice_steal: default_voice_steal(),
+                quality: default_quality(),
+                smoothing_ms: default_smoothing_ms(),
             },
         }
     }
@@ -86,14 +219,76 @@ impl Default for SynthState {
 
 pub struct SynthEngine {
     pub state: Arc<Mutex<SynthState>>,
+    /// Write end of the running stream's state buffer, if any.
+    live: Mutex<Option<Input<SynthState>>>,
 }
 
 impl Default for SynthEngine {
     fn default() -> Self {
         Self {
             state: Arc::new(Mutex::new(SynthState::default())),
+            live: Mutex::new(None),
+        }
+    }
+}
+
+impl SynthEngine {
+    /// Opens a fresh state buffer for a new audio stream and returns its
+    /// read end, already holding the current state.
+    pub fn connect(&self) -> Result<Output<SynthState>, String> {
+        let current = self
+            .state
+            .lock()
+            .map_err(|_| "synth state lock poisoned".to_string())?
+            .clone();
+        let (input, output) = state_buffer(&current);
+        *self
+            .live
+            .lock()
+            .map_err(|_| "synth state lock poisoned".to_string())? = Some(input);
+        Ok(output)
+    }
+
+    /// Hands `state` to the audio thread, if a stream is connected.
+    pub fn publish(&self, state: &SynthState) {
+        if let Ok(mut live) = self.live.lock() {
+            if let Some(input) = live.as_mut() {
+                input.write(prepared(state));
+            }
         }
     }
+
+    /// Publishes the state again, replacing whatever automation wrote into
+    /// the stream's copy.
+    pub fn republish(&self) -> Result<(), String> {
+        self.update(|_| ())
+    }
+
+    fn update<R>(&self, change: impl FnOnce(&mut SynthState) -> R) -> Result<R, String> {
+        let mut guard = self
+            .state
+            .lock()
+            .map_err(|_| "synth state lock poisoned".to_string())?;
+        let result = change(&mut guard);
+        self.publish(&guard);
+        Ok(result)
+    }
+}
+
+/// A copy of `state` the audio thread can automate without allocating.
+pub fn prepared(state: &SynthState) -> SynthState {
+    let mut copy = state.clone();
+    params::reserve_choices(&mut copy);
+    copy
+}
+
+/// Triple buffer whose read end starts on a [`prepared`] copy of `state`.
+/// The buffer's own initial slots are plain clones, so the first read must
+/// swap one out before the audio thread writes to it.
+pub fn state_buffer(state: &SynthState) -> (Input<SynthState>, Output<SynthState>) {
+    let (mut input, output) = TripleBuffer::new(state).split();
+    input.write(prepared(state));
+    (input, output)
 }
 
 #[tauri::command]
@@ -107,19 +302,36 @@ pub fn synth_get_state(state: State<SynthEngine>) -> SynthState {
 
 #[tauri::command]
 pub fn synth_set_state(next: SynthState, state: State<SynthEngine>) -> Result<(), String> {
+    state.update(|s| s.clone_from(&next))
+}
+
+#[tauri::command]
+pub fn synth_reset(state: State<SynthEngine>) -> SynthState {
+    let default_state = SynthState::default();
+    let _ = state.update(|s| *s = default_state.clone());
+    default_state
+}
+
+#[tauri::command]
+pub fn synth_list_parameters() -> Vec<ParameterInfo> {
+    params::list()
+}
+
+#[tauri::command]
+pub fn synth_get_parameter(path: String, state: State<SynthEngine>) -> Result<Value, String> {
     state
         .state
         .lock()
         .map_err(|_| "synth state lock poisoned".to_string())?
-        .clone_from(&next);
-    Ok(())
+        .get_path(&path)
+        .ok_or_else(|| format!("unknown parameter path {path:?}"))
 }
 
 #[tauri::command]
-pub fn synth_reset(state: State<SynthEngine>) -> SynthState {
-    let default_state = SynthState::default();
-    if let Ok(mut guard) = state.state.lock() {
-        *guard = default_state.clone();
-    }
-    default_state
+pub fn synth_set_parameter(
+    path: String,
+    value: Value,
+    state: State<SynthEngine>,
+) -> Result<(), String> {
+    state.update(|s| s.set_path(&path, &value))?
 }
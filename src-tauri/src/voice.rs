@@ -0,0 +1,163 @@
+use crate::dsp::Params;
+use crate::envelope::Adsr;
+use crate::filter::Svf;
+use crate::noise::Noise;
+use crate::oscillator::Waveform;
+use crate::stereo::{pan_gains, UnisonLayout, MAX_UNISON};
+
+/// A glide covers all but 0.1% of the interval within the glide time.
+const GLIDE_SETTLE: f32 = 6.907_755;
+/// Cutoff sweep in octaves for a full envelope at `env_amount == 1.0`.
+const FILTER_ENV_OCTAVES: f32 = 6.0;
+/// Input gain into the saturator at `drive == 1.0`, minus one.
+const DRIVE_GAIN: f32 = 9.0;
+/// Golden-ratio step between unison copies' starting phases, so a fresh
+/// stack doesn't start phase-aligned.
+const UNISON_PHASE_STEP: f32 = 0.618_034;
+/// Notes this many semitones either side of middle C sit at the edges when
+/// `pan_spread` is 1.
+const PAN_SPREAD_SEMITONES: f32 = 24.0;
+
+/// One note's worth of oscillator, filter and amplitude envelope.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Voice {
+    pub note: u8,
+    /// Sounding pitch in semitones; trails `note` while gliding.
+    pitch: f32,
+    velocity: f32,
+    /// Engine clock at the last trigger, used to find the oldest voice.
+    pub started_at: u64,
+    /// Oscillator phases per unison copy.
+    phase: [f32; MAX_UNISON],
+    phase2: [f32; MAX_UNISON],
+    sub_phase: f32,
+    /// Left and right filters.
+    filter: [Svf; 2],
+    envelope: Adsr,
+    noise: Noise,
+    gate: bool,
+}
+
+impl Voice {
+    /// Triggers the envelope at `note`. With `glide` the pitch slides from
+    /// wherever the voice last was instead of jumping.
+    pub fn start(&mut self, note: u8, velocity: f32, started_at: u64, glide: bool) {
+        self.note = note;
+        if !glide {
+            self.pitch = note as f32;
+        }
+        if !self.is_active() {
+            // The first copy keeps its phase, so one copy plays as before.
+            for i in 1..MAX_UNISON {
+                self.phase[i] = (i as f32 * UNISON_PHASE_STEP).fract();
+                self.phase2[i] = self.phase[i];
+            }
+        }
+        self.noise.seed(started_at as u32);
+        self.velocity = velocity;
+        self.started_at = started_at;
+        self.gate = true;
+        self.envelope.gate_on();
+    }
+
+    /// Slides a sounding voice to another note without retriggering it.
+    pub fn retune(&mut self, note: u8) {
+        self.note = note;
+    }
+
+    pub fn release(&mut self) {
+        self.gate = false;
+        self.envelope.gate_off();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.envelope.is_active()
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.gate
+    }
+
+    pub fn level(&self) -> f32 {
+        self.envelope.level() * self.velocity
+    }
+
+    /// Next left/right sample pair. With `pan_spread` the whole voice is
+    /// balanced towards the side its note lies on.
+    pub fn next(&mut self, p: &Params, layout: &UnisonLayout, sample_rate: f32) -> (f32, f32) {
+        let target = self.note as f32;
+        if p.glide_time > 0.0 {
+            let coeff = (-GLIDE_SETTLE / (p.glide_time * sample_rate)).exp();
+            self.pitch = target + (self.pitch - target) * coeff;
+        } else {
+            self.pitch = target;
+        }
+        let freq = pitch_frequency(self.pitch + p.tune);
+        let env = self.envelope.next(&p.envelope, sample_rate);
+
+        let cutoff = p.cutoff * 2.0f32.powf(p.env_amount * FILTER_ENV_OCTAVES * env);
+        let freq2 = pitch_frequency(self.pitch + p.tune2);
+        let (mut left, mut right) = (0.0, 0.0);
+        for i in 0..layout.count {
+            let (f1, f2) = (freq * layout.ratios[i], freq2 * layout.ratios[i]);
+            let advanced = self.phase[i] + f1 / sample_rate;
+            self.phase[i] = advanced % 1.0;
+            self.phase2[i] = if p.sync && advanced >= 1.0 {
+                // Restart at the fraction of a slave cycle elapsed since the
+                // master wrapped, rather than at zero, to keep the reset in time.
+                (self.phase[i] * f2 / f1) % 1.0
+            } else {
+                (self.phase2[i] + f2 / sample_rate) % 1.0
+            };
+            let osc1 = p.waveform.sample(self.phase[i], f1 / sample_rate, p.quality);
+            let osc2 = p.waveform2.sample(self.phase2[i], f2 / sample_rate, p.quality);
+            let copy = osc1 * p.level + osc2 * p.level2;
+            let (gain_l, gain_r) = layout.gains[i];
+            left += copy * gain_l;
+            right += copy * gain_r;
+        }
+        self.sub_phase = (self.sub_phase + freq / p.sub_divisor / sample_rate) % 1.0;
+        let sub_freq = freq / p.sub_divisor;
+        let center = Waveform::Square.sample(self.sub_phase, sub_freq / sample_rate, p.quality)
+            * p.sub
+            + self.noise.next(p.noise_color) * p.noise;
+        let (center_l, center_r) = layout.center;
+        let mut mix = [
+            left * layout.normalize + center * center_l,
+            right * layout.normalize + center * center_r,
+        ];
+        if p.pan_spread > 0.0 {
+            let position = (self.pitch - 60.0) / PAN_SPREAD_SEMITONES;
+            let (balance_l, balance_r) = pan_gains(position * p.pan_spread);
+            mix[0] *= balance_l;
+            mix[1] *= balance_r;
+        }
+        let [out_l, out_r] = [0, 1].map(|channel| {
+            self.filter[channel].process(
+                saturate(mix[channel], p.drive),
+                cutoff,
+                p.resonance,
+                p.filter_mode,
+                p.filter_slope,
+                sample_rate,
+            ) * env
+                * self.velocity
+        });
+        (out_l, out_r)
+    }
+}
+
+/// Pre-filter tanh saturation, crossfaded in by `drive` so zero is bypass.
+fn saturate(x: f32, drive: f32) -> f32 {
+    if drive <= 0.0 {
+        return x;
+    }
+    let gain = 1.0 + drive * DRIVE_GAIN;
+    let shaped = (x * gain).tanh() / gain.tanh();
+    x + (shaped - x) * drive
+}
+
+/// Equal-tempered frequency of a (fractional) MIDI note number.
+pub fn pitch_frequency(pitch: f32) -> f32 {
+    440.0 * 2.0f32.powf((pitch - 69.0) / 12.0)
+}
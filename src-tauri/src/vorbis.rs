@@ -0,0 +1,987 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::f64::consts::PI;
+use std::io::{self, Write};
+
+use crate::flac::vorbis_comments;
+use crate::ogg::PageWriter;
+
+/// Every packet is one block of this many samples, half of it overlapping
+/// the previous block.
+const BLOCK: usize = 2048;
+const HALF: usize = BLOCK / 2;
+/// Floor 1 posts are stored at half of full resolution (`multiplier` 2),
+/// which gives 128 levels about 1.1 dB apart.
+const FLOOR_MULTIPLIER: i32 = 2;
+const FLOOR_RANGE: i32 = 128;
+const FLOOR_RANGE_BITS: u32 = 10;
+/// Posts between the two fixed ones at either end of the spectrum.
+const FLOOR_POSTS: usize = 48;
+const FLOOR_CLASS_DIMENSIONS: usize = 4;
+/// The decoder's floor levels: `DB_BASE * exp(DB_STEP * index)`.
+const DB_BASE: f64 = 1.064_986_3e-7;
+const DB_STEP: f64 = 0.062_961_31;
+const MDCT_SCALE: f64 = 4.0 / BLOCK as f64;
+/// Spectral lines per residue partition.
+const PARTITION: usize = 16;
+const PARTITIONS: usize = HALF / PARTITION;
+/// Largest residue the coarse and medium books can add up to.
+const MAX_RESIDUE: i32 = 127;
+const COARSE_STEP: i32 = 17;
+/// How far below the local spectral peak the quantization step sits.
+const SIGNAL_TO_NOISE_DB: f64 = 24.0;
+/// Spectral level treated as silence, about 100 dB below a full-scale sine.
+const SILENCE: f64 = 1e-5;
+
+/// Codebook numbers in the setup header.
+const FLOOR_BOOK: usize = 0;
+const CLASS_BOOK: usize = 1;
+const UNIT_BOOK: usize = 2;
+const SMALL_BOOK: usize = 3;
+const MEDIUM_BOOK: usize = 4;
+const COARSE_BOOK: usize = 5;
+
+/// Residue partition classes: the largest value each can code and its
+/// codebook per pass. The last adds a medium correction to a coarse step.
+const RESIDUE_CLASSES: [(i32, &[usize]); 5] = [
+    (0, &[]),
+    (1, &[UNIT_BOOK]),
+    (3, &[SMALL_BOOK]),
+    (8, &[MEDIUM_BOOK]),
+    (MAX_RESIDUE, &[COARSE_BOOK, MEDIUM_BOOK]),
+];
+
+/// Streaming Ogg Vorbis encoder. Uses a single block size, floor 1 with a
+/// fixed set of posts and a fixed residue codebook set, so the bitrate
+/// follows the signal rather than a target; it is meant for previews and
+/// sample libraries, not for competing with the reference encoder.
+///
+/// Samples are written interleaved, as floats in `-1.0..=1.0`.
+pub struct VorbisWriter<W: Write> {
+    pages: PageWriter<W>,
+    /// The block being filled per channel. Its first half is the overlap
+    /// with the previous block; new samples go into the second half.
+    blocks: Vec<Vec<f32>>,
+    filled: usize,
+    next_channel: usize,
+    /// Packets written so far, the headers aside.
+    packets: u64,
+    total_samples: u64,
+    setup: Setup,
+    mdct: Mdct,
+    window: Vec<f64>,
+}
+
+impl<W: Write> VorbisWriter<W> {
+    /// `tags` become Vorbis comments (`TITLE`, ...).
+    pub fn new(
+        out: W,
+        sample_rate: u32,
+        channels: u16,
+        tags: &[(&str, String)],
+    ) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        if !(1..=8).contains(&channels) {
+            return Err(invalid(format!(
+                "Vorbis export supports 1 to 8 channels, not {channels}"
+            )));
+        }
+        if sample_rate == 0 {
+            return Err(invalid("sample rate can't be 0".into()));
+        }
+
+        let setup = Setup::new();
+        // Any serial number will do for a file with a single stream.
+        let mut pages = PageWriter::new(out, sample_rate ^ 0x616e_6472);
+        pages.write_packet(&identification(sample_rate, channels), 0, true, false)?;
+        let mut comment = b"\x03vorbis".to_vec();
+        comment.extend_from_slice(&vorbis_comments(tags));
+        comment.push(1);
+        pages.write_packet(&comment, 0, false, false)?;
+        pages.write_packet(&setup.header(), 0, true, false)?;
+
+        let window = (0..BLOCK)
+            .map(|i| {
+                let s = (PI * (i as f64 + 0.5) / BLOCK as f64).sin();
+                (PI / 2.0 * s * s).sin()
+            })
+            .collect();
+        Ok(Self {
+            pages,
+            blocks: vec![vec![0.0; BLOCK]; channels as usize],
+            filled: 0,
+            next_channel: 0,
+            packets: 0,
+            total_samples: 0,
+            setup,
+            mdct: Mdct::new(),
+            window,
+        })
+    }
+
+    pub fn write_sample(&mut self, sample: f32) -> io::Result<()> {
+        self.blocks[self.next_channel][HALF + self.filled] = sample;
+        self.next_channel = (self.next_channel + 1) % self.blocks.len();
+        if self.next_channel == 0 {
+            self.filled += 1;
+            self.total_samples += 1;
+            if self.filled == HALF {
+                self.write_block(false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Pads the last block with silence and closes the stream. The final
+    /// granule position tells decoders to drop the padding.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.next_channel != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Vorbis stream ended mid-frame",
+            ));
+        }
+        // Packet `k` completes the samples before `k * HALF`, and the first
+        // packet only primes the overlap.
+        let packets = self.total_samples.div_ceil(HALF as u64) + 1;
+        while self.packets < packets {
+            for block in &mut self.blocks {
+                block[HALF + self.filled..].fill(0.0);
+            }
+            self.write_block(self.packets + 1 == packets)?;
+        }
+        let mut out = self.pages.into_inner();
+        out.flush()?;
+        Ok(out)
+    }
+
+    fn write_block(&mut self, last: bool) -> io::Result<()> {
+        let mut w = BitWriter::default();
+        w.write(0, 1); // audio packet; the only mode takes no bits
+        let mut residues = Vec::with_capacity(self.blocks.len());
+        for block in &self.blocks {
+            let windowed: Vec<f64> = block
+                .iter()
+                .zip(&self.window)
+                .map(|(&s, &w)| s as f64 * w * MDCT_SCALE)
+                .collect();
+            let spectrum = self.mdct.forward(&windowed);
+            residues.push(self.setup.encode_floor(&mut w, &spectrum));
+        }
+        self.setup.encode_residue(&mut w, &residues);
+
+        let granule = if last {
+            self.total_samples
+        } else {
+            self.packets * HALF as u64
+        };
+        // Closing the page after the priming packet gives decoders a
+        // granule position to count from, which they need to trim the end.
+        let flush = self.packets == 0;
+        self.pages
+            .write_packet(&w.into_bytes(), granule, flush, last)?;
+        self.packets += 1;
+        for block in &mut self.blocks {
+            block.copy_within(HALF.., 0);
+        }
+        self.filled = 0;
+        Ok(())
+    }
+}
+
+fn identification(sample_rate: u32, channels: u16) -> Vec<u8> {
+    let mut packet = b"\x01vorbis".to_vec();
+    packet.extend_from_slice(&0u32.to_le_bytes());
+    packet.push(channels as u8);
+    packet.extend_from_slice(&sample_rate.to_le_bytes());
+    // No maximum, nominal or minimum bitrate.
+    packet.extend_from_slice(&[0; 12]);
+    let size = BLOCK.trailing_zeros() as u8;
+    packet.push(size << 4 | size);
+    packet.push(1);
+    packet
+}
+
+/// The codebooks, floor and residue setup shared by every packet.
+struct Setup {
+    books: Vec<Codebook>,
+    /// Floor post positions in header order; the first two are the ends.
+    posts: Vec<i32>,
+    /// Per post, the earlier posts either side of it.
+    neighbors: Vec<(usize, usize)>,
+    /// Post indices in order of position.
+    sorted: Vec<usize>,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let floor_weight = |v: i32| 1.0 / ((1 + v) as f64).powi(2);
+        let class_weight = |c: i32| [4.0, 3.0, 2.0, 2.0, 1.0][c as usize];
+        // Smaller values are likelier within each book.
+        let falloff = |scale: f64| move |v: i32| (-(v.abs() as f64) / scale).exp();
+        let coarse_values = 2 * (MAX_RESIDUE / COARSE_STEP) + 1;
+        let books = vec![
+            Codebook::scalar(1, FLOOR_RANGE, floor_weight),
+            Codebook::scalar(2, RESIDUE_CLASSES.len() as i32, class_weight),
+            Codebook::lattice(4, -1, 1, 3, falloff(1.0)),
+            Codebook::lattice(2, -3, 1, 7, falloff(1.5)),
+            Codebook::lattice(2, -8, 1, 17, falloff(8.0)),
+            Codebook::lattice(
+                2,
+                -(MAX_RESIDUE / COARSE_STEP) * COARSE_STEP,
+                COARSE_STEP,
+                coarse_values,
+                falloff(COARSE_STEP as f64),
+            ),
+        ];
+
+        // Even on a log scale of bins, so low bins, each a large fraction of
+        // an octave, get posts of their own.
+        let mut inner = Vec::with_capacity(FLOOR_POSTS);
+        for i in 0..FLOOR_POSTS {
+            let t = i as f64 / (FLOOR_POSTS - 1) as f64;
+            let x = (2.0f64.ln() + t * (1000.0f64.ln() - 2.0f64.ln()))
+                .exp()
+                .round() as i32;
+            let previous = inner.last().copied().unwrap_or(0);
+            inner.push(x.max(previous + 1));
+        }
+        // Header order halves the gaps breadth first, so each post is
+        // predicted from close neighbours.
+        let mut posts = vec![0, HALF as i32];
+        let mut spans = std::collections::VecDeque::from([(0, inner.len())]);
+        while let Some((start, end)) = spans.pop_front() {
+            if start < end {
+                let mid = (start + end) / 2;
+                posts.push(inner[mid]);
+                spans.push_back((start, mid));
+                spans.push_back((mid + 1, end));
+            }
+        }
+
+        let neighbors = (0..posts.len())
+            .map(|i| {
+                let x = posts[i];
+                let earlier = || posts[..i].iter().copied().enumerate();
+                let low = earlier().filter(|&(_, p)| p < x).max_by_key(|&(_, p)| p);
+                let high = earlier().filter(|&(_, p)| p > x).min_by_key(|&(_, p)| p);
+                (low.map_or(0, |(j, _)| j), high.map_or(0, |(j, _)| j))
+            })
+            .collect();
+        let mut sorted: Vec<usize> = (0..posts.len()).collect();
+        sorted.sort_by_key(|&i| posts[i]);
+        Self {
+            books,
+            posts,
+            neighbors,
+            sorted,
+        }
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        for &byte in b"\x05vorbis" {
+            w.write(byte as u64, 8);
+        }
+        w.write(self.books.len() as u64 - 1, 8);
+        for book in &self.books {
+            book.write_header(&mut w);
+        }
+        // One placeholder time-domain transform.
+        w.write(0, 6);
+        w.write(0, 16);
+
+        // Floor 1: every partition uses class 0, four posts read straight
+        // from the floor book.
+        w.write(0, 6);
+        w.write(1, 16);
+        let partitions = FLOOR_POSTS / FLOOR_CLASS_DIMENSIONS;
+        w.write(partitions as u64, 5);
+        for _ in 0..partitions {
+            w.write(0, 4);
+        }
+        w.write(FLOOR_CLASS_DIMENSIONS as u64 - 1, 3);
+        w.write(0, 2); // no subclasses
+        w.write(FLOOR_BOOK as u64 + 1, 8);
+        w.write(FLOOR_MULTIPLIER as u64 - 1, 2);
+        w.write(FLOOR_RANGE_BITS as u64, 4);
+        for &x in &self.posts[2..] {
+            w.write(x as u64, FLOOR_RANGE_BITS);
+        }
+
+        // Residue 1 over the whole spectrum with four classes: silent, unit
+        // steps, small values, and a coarse pass refined by a fine one.
+        w.write(0, 6);
+        w.write(1, 16);
+        w.write(0, 24);
+        w.write(HALF as u64, 24);
+        w.write(PARTITION as u64 - 1, 24);
+        w.write(RESIDUE_CLASSES.len() as u64 - 1, 6);
+        w.write(CLASS_BOOK as u64, 8);
+        for (_, books) in RESIDUE_CLASSES {
+            w.write((1 << books.len()) - 1, 3);
+            w.write(0, 1);
+        }
+        for (_, books) in RESIDUE_CLASSES {
+            for &book in books {
+                w.write(book as u64, 8);
+            }
+        }
+
+        // One mapping: no coupling, every channel through floor 0 and
+        // residue 0.
+        w.write(0, 6);
+        w.write(0, 16);
+        w.write(0, 1);
+        w.write(0, 1);
+        w.write(0, 2);
+        w.write(0, 8);
+        w.write(0, 8);
+        w.write(0, 8);
+
+        // One mode: short blocks through mapping 0.
+        w.write(0, 6);
+        w.write(0, 1);
+        w.write(0, 16);
+        w.write(0, 16);
+        w.write(0, 8);
+        w.write(1, 1);
+        w.into_bytes()
+    }
+
+    /// Writes the floor for one channel and returns its residue, or `None`
+    /// if the channel is silent and the floor was marked unused.
+    fn encode_floor(&self, w: &mut BitWriter, spectrum: &[f64]) -> Option<Vec<i32>> {
+        let peak = spectrum.iter().fold(0.0f64, |peak, x| peak.max(x.abs()));
+        if peak < SILENCE / 4.0 {
+            w.write(0, 1);
+            return None;
+        }
+
+        // Aim each post just under the loudest line it influences.
+        let snr = 10f64.powf(-SIGNAL_TO_NOISE_DB / 20.0);
+        let mut targets = vec![0; self.posts.len()];
+        for (j, &i) in self.sorted.iter().enumerate() {
+            let start = if j == 0 {
+                0
+            } else {
+                self.posts[self.sorted[j - 1]]
+            };
+            let end = self
+                .sorted
+                .get(j + 1)
+                .map_or(HALF as i32, |&k| self.posts[k]);
+            let loudest = spectrum[start as usize..end as usize]
+                .iter()
+                .fold(0.0f64, |peak, x| peak.max(x.abs()));
+            let level = (loudest * snr).max(SILENCE);
+            let index = ((level / DB_BASE).ln() / DB_STEP / FLOOR_MULTIPLIER as f64).floor();
+            targets[i] = (index as i32).clamp(0, FLOOR_RANGE - 1);
+        }
+
+        // Code each post relative to the decoder's prediction from its
+        // neighbours, tracking the values the decoder will arrive at.
+        let mut values = targets.clone();
+        let mut drawn = vec![false; self.posts.len()];
+        drawn[0] = true;
+        drawn[1] = true;
+        let mut codes = Vec::with_capacity(self.posts.len() - 2);
+        for i in 2..self.posts.len() {
+            let (low, high) = self.neighbors[i];
+            let predicted = render_point(
+                self.posts[low],
+                values[low],
+                self.posts[high],
+                values[high],
+                self.posts[i],
+            );
+            let (code, value) = floor_code(predicted, targets[i]);
+            if code != 0 {
+                drawn[low] = true;
+                drawn[high] = true;
+                drawn[i] = true;
+            }
+            values[i] = value;
+            codes.push(code);
+        }
+
+        w.write(1, 1);
+        let bits = 32 - (FLOOR_RANGE as u32 - 1).leading_zeros();
+        w.write(values[0] as u64, bits);
+        w.write(values[1] as u64, bits);
+        for code in codes {
+            self.books[FLOOR_BOOK].write(w, code as usize);
+        }
+
+        // Draw the curve the decoder will, and scale the spectrum by it.
+        let mut curve = Vec::with_capacity(HALF);
+        let (mut lx, mut ly) = (0, values[0] * FLOOR_MULTIPLIER);
+        for &i in &self.sorted[1..] {
+            if drawn[i] {
+                let (hx, hy) = (self.posts[i], values[i] * FLOOR_MULTIPLIER);
+                render_line(lx, ly, hx, hy, &mut curve);
+                (lx, ly) = (hx, hy);
+            }
+        }
+        Some(
+            spectrum
+                .iter()
+                .zip(curve)
+                .map(|(x, y)| {
+                    let floor = DB_BASE * (DB_STEP * y as f64).exp();
+                    ((x / floor).round() as i32).clamp(-MAX_RESIDUE, MAX_RESIDUE)
+                })
+                .collect(),
+        )
+    }
+
+    /// Writes the residues of the channels whose floors are in use, in the
+    /// order the decoder reads them.
+    fn encode_residue(&self, w: &mut BitWriter, residues: &[Option<Vec<i32>>]) {
+        let coded: Vec<&Vec<i32>> = residues.iter().flatten().collect();
+        let classes: Vec<Vec<usize>> = coded
+            .iter()
+            .map(|residue| {
+                residue
+                    .chunks(PARTITION)
+                    .map(|part| {
+                        let largest = part.iter().map(|r| r.abs()).max().unwrap_or(0);
+                        RESIDUE_CLASSES
+                            .iter()
+                            .position(|&(max, _)| largest <= max)
+                            .unwrap_or(RESIDUE_CLASSES.len() - 1)
+                    })
+                    .collect()
+            })
+            .collect();
+        let per_word = self.books[CLASS_BOOK].dimensions;
+        for pass in 0..2 {
+            for partition in 0..PARTITIONS {
+                if pass == 0 && partition % per_word == 0 {
+                    for channel in &classes {
+                        let word = channel[partition..partition + per_word]
+                            .iter()
+                            .fold(0, |word, &class| word * RESIDUE_CLASSES.len() + class);
+                        self.books[CLASS_BOOK].write(w, word);
+                    }
+                }
+                for (residue, channel) in coded.iter().zip(&classes) {
+                    let part = &residue[partition * PARTITION..(partition + 1) * PARTITION];
+                    let books = RESIDUE_CLASSES[channel[partition]].1;
+                    let Some(&book) = books.get(pass) else {
+                        continue;
+                    };
+                    let values: Vec<i32> = match (books.len(), pass) {
+                        (1, _) => part.to_vec(),
+                        (_, 0) => part.iter().map(|&r| coarse(r) * COARSE_STEP).collect(),
+                        _ => part.iter().map(|&r| r - coarse(r) * COARSE_STEP).collect(),
+                    };
+                    self.books[book].write_vectors(w, &values);
+                }
+            }
+        }
+    }
+}
+
+/// Nearest multiple of the coarse step, in steps.
+fn coarse(residue: i32) -> i32 {
+    (residue as f64 / COARSE_STEP as f64).round() as i32
+}
+
+/// The floor 1 code for moving from `predicted` to `target`, and the value
+/// the decoder will end up with (which is `target`).
+fn floor_code(predicted: i32, target: i32) -> (i32, i32) {
+    let high_room = FLOOR_RANGE - predicted;
+    let low_room = predicted;
+    let room = 2 * high_room.min(low_room);
+    let delta = target - predicted;
+    let code = match delta {
+        0 => 0,
+        d if d > 0 && 2 * d < room => 2 * d,
+        d if d < 0 && -2 * d - 1 < room => -2 * d - 1,
+        d if high_room > low_room => d + low_room,
+        d => -d + high_room - 1,
+    };
+    (code, target)
+}
+
+fn render_point(x0: i32, y0: i32, x1: i32, y1: i32, x: i32) -> i32 {
+    let dy = y1 - y0;
+    let offset = dy.abs() * (x - x0) / (x1 - x0);
+    if dy < 0 {
+        y0 - offset
+    } else {
+        y0 + offset
+    }
+}
+
+/// The decoder's integer line from `x0` up to, not including, `x1`.
+fn render_line(x0: i32, y0: i32, x1: i32, y1: i32, out: &mut Vec<i32>) {
+    let dy = y1 - y0;
+    let adx = x1 - x0;
+    let base = dy / adx;
+    let step = if dy < 0 { base - 1 } else { base + 1 };
+    let ady = dy.abs() - base.abs() * adx;
+    let (mut y, mut err) = (y0, 0);
+    out.push(y);
+    for _ in x0 + 1..x1 {
+        err += ady;
+        if err >= adx {
+            err -= adx;
+            y += step;
+        } else {
+            y += base;
+        }
+        out.push(y);
+    }
+}
+
+/// A Huffman codebook, optionally mapping entries to vectors on a lattice.
+struct Codebook {
+    dimensions: usize,
+    lengths: Vec<u8>,
+    codewords: Vec<u32>,
+    /// Lowest value, step between values and values per dimension.
+    lattice: Option<(i32, i32, i32)>,
+}
+
+impl Codebook {
+    /// Entries are the numbers `0..count` taken `dimensions` at a time, the
+    /// first most significant, as used for class words.
+    fn scalar(dimensions: usize, count: i32, weight: impl Fn(i32) -> f64) -> Self {
+        let entries = (count as usize).pow(dimensions as u32);
+        let weights = (0..entries).map(|entry| {
+            let mut rest = entry as i32;
+            (0..dimensions).fold(1.0, |product, _| {
+                let value = rest % count;
+                rest /= count;
+                product * weight(value)
+            })
+        });
+        Self::with_weights(dimensions, weights.collect(), None)
+    }
+
+    /// Entries are vectors of `count` values from `min` in steps of `step`;
+    /// `weight` gets each value's index.
+    fn lattice(
+        dimensions: usize,
+        min: i32,
+        step: i32,
+        count: i32,
+        weight: impl Fn(i32) -> f64,
+    ) -> Self {
+        let entries = (count as usize).pow(dimensions as u32);
+        let weights = (0..entries).map(|entry| {
+            let mut rest = entry as i32;
+            (0..dimensions).fold(1.0, |product, _| {
+                let value = min + rest % count * step;
+                rest /= count;
+                product * weight(value)
+            })
+        });
+        Self::with_weights(dimensions, weights.collect(), Some((min, step, count)))
+    }
+
+    fn with_weights(
+        dimensions: usize,
+        weights: Vec<f64>,
+        lattice: Option<(i32, i32, i32)>,
+    ) -> Self {
+        let lengths = huffman_lengths(&weights);
+        let codewords = codewords(&lengths);
+        Self {
+            dimensions,
+            lengths,
+            codewords,
+            lattice,
+        }
+    }
+
+    fn write_header(&self, w: &mut BitWriter) {
+        w.write(0x56_4342, 24);
+        w.write(self.dimensions as u64, 16);
+        w.write(self.lengths.len() as u64, 24);
+        w.write(0, 1); // not ordered
+        w.write(0, 1); // every entry used
+        for &length in &self.lengths {
+            w.write(length as u64 - 1, 5);
+        }
+        match self.lattice {
+            None => w.write(0, 4),
+            Some((min, step, count)) => {
+                w.write(1, 4);
+                w.write(float32_pack(min as f64) as u64, 32);
+                w.write(float32_pack(step as f64) as u64, 32);
+                let bits = 32 - (count as u32 - 1).leading_zeros();
+                w.write(bits as u64 - 1, 4);
+                w.write(0, 1); // values don't accumulate
+                for multiplicand in 0..count {
+                    w.write(multiplicand as u64, bits);
+                }
+            }
+        }
+    }
+
+    fn write(&self, w: &mut BitWriter, entry: usize) {
+        let (codeword, length) = (self.codewords[entry], self.lengths[entry]);
+        // Codewords are read a bit at a time from the most significant end.
+        for bit in (0..length).rev() {
+            w.write((codeword >> bit) as u64 & 1, 1);
+        }
+    }
+
+    /// Writes `values`, which must lie on the lattice, as consecutive
+    /// vectors.
+    fn write_vectors(&self, w: &mut BitWriter, values: &[i32]) {
+        let (min, step, count) = self.lattice.expect("vector codebook");
+        for vector in values.chunks(self.dimensions) {
+            let entry = vector
+                .iter()
+                .rev()
+                .fold(0, |entry, &v| entry * count + (v - min) / step);
+            self.write(w, entry as usize);
+        }
+    }
+}
+
+/// Code lengths of a Huffman code for `weights`. Every length is at least
+/// one, so the tree is always complete as decoders require.
+fn huffman_lengths(weights: &[f64]) -> Vec<u8> {
+    let total: f64 = weights.iter().sum();
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = weights
+        .iter()
+        .enumerate()
+        .map(|(i, w)| Reverse((((w / total) * (1u64 << 40) as f64) as u64 + 1, i)))
+        .collect();
+    let mut parents = vec![usize::MAX; weights.len()];
+    while heap.len() > 1 {
+        let (Some(Reverse((a, i))), Some(Reverse((b, j)))) = (heap.pop(), heap.pop()) else {
+            unreachable!()
+        };
+        let node = parents.len();
+        parents.push(usize::MAX);
+        parents[i] = node;
+        parents[j] = node;
+        heap.push(Reverse((a + b, node)));
+    }
+    (0..weights.len())
+        .map(|leaf| {
+            let mut depth = 0;
+            let mut node = leaf;
+            while parents[node] != usize::MAX {
+                node = parents[node];
+                depth += 1;
+            }
+            depth
+        })
+        .collect()
+}
+
+/// Assigns each entry, in order, the lowest free codeword of its length,
+/// which is how decoders rebuild the tree from the lengths alone.
+fn codewords(lengths: &[u8]) -> Vec<u32> {
+    // `marker[n]` is the next free codeword of length `n`.
+    let mut marker = [0u32; 33];
+    lengths
+        .iter()
+        .map(|&length| {
+            let length = length as usize;
+            let codeword = marker[length];
+            for j in (1..=length).rev() {
+                if marker[j] & 1 != 0 {
+                    marker[j] = if j == 1 {
+                        marker[1] + 1
+                    } else {
+                        marker[j - 1] << 1
+                    };
+                    break;
+                }
+                marker[j] += 1;
+            }
+            // Codewords that start with the one just used are taken too.
+            let mut taken = codeword;
+            for j in length + 1..33 {
+                if marker[j] >> 1 != taken {
+                    break;
+                }
+                taken = marker[j];
+                marker[j] = marker[j - 1] << 1;
+            }
+            codeword
+        })
+        .collect()
+}
+
+/// Vorbis' float format: a 21-bit mantissa and a biased 10-bit exponent.
+fn float32_pack(value: f64) -> u32 {
+    if value == 0.0 {
+        return 0;
+    }
+    let sign = if value < 0.0 { 1 << 31 } else { 0 };
+    let exponent = value.abs().log2().floor() as i32;
+    let mantissa = (value.abs() * 2f64.powi(20 - exponent)).round() as u32;
+    sign | ((exponent + 768) as u32) << 21 | mantissa
+}
+
+/// MDCT of a full block, computed as a DCT-IV of the folded input through
+/// a complex FFT of a quarter of the block.
+struct Mdct {
+    /// Rotations before and after the FFT.
+    pre: Vec<(f64, f64)>,
+    post: Vec<(f64, f64)>,
+    /// FFT roots of unity.
+    roots: Vec<(f64, f64)>,
+}
+
+impl Mdct {
+    fn new() -> Self {
+        let quarter = BLOCK / 4;
+        let rotation = |offset: f64| -> Vec<(f64, f64)> {
+            (0..quarter)
+                .map(|n| {
+                    let angle = -PI * (n as f64 + offset) / HALF as f64;
+                    (angle.cos(), angle.sin())
+                })
+                .collect()
+        };
+        let roots = (0..quarter / 2)
+            .map(|k| {
+                let angle = -2.0 * PI * k as f64 / quarter as f64;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+        Self {
+            pre: rotation(0.25),
+            post: rotation(0.0),
+            roots,
+        }
+    }
+
+    fn forward(&self, input: &[f64]) -> Vec<f64> {
+        let (quarter, half) = (BLOCK / 4, HALF);
+        // Fold the four quarters (a, b, c, d) into (-c_r - d, a - b_r).
+        let folded: Vec<f64> = (0..half)
+            .map(|n| {
+                if n < quarter {
+                    -input[3 * quarter - 1 - n] - input[3 * quarter + n]
+                } else {
+                    input[n - quarter] - input[3 * quarter - 1 - n]
+                }
+            })
+            .collect();
+
+        let mut z: Vec<(f64, f64)> = (0..quarter)
+            .map(|n| {
+                let (re, im) = (folded[2 * n], folded[half - 1 - 2 * n]);
+                let (c, s) = self.pre[n];
+                (re * c - im * s, re * s + im * c)
+            })
+            .collect();
+        self.fft(&mut z);
+
+        let mut out = vec![0.0; half];
+        for (k, &(re, im)) in z.iter().enumerate() {
+            let (c, s) = self.post[k];
+            let (yr, yi) = (re * c - im * s, re * s + im * c);
+            out[2 * k] = yr;
+            out[half - 1 - 2 * k] = -yi;
+        }
+        out
+    }
+
+    /// In-place radix-2 FFT.
+    fn fft(&self, data: &mut [(f64, f64)]) {
+        let n = data.len();
+        let mut j = 0;
+        for i in 1..n {
+            let mut bit = n >> 1;
+            while j & bit != 0 {
+                j ^= bit;
+                bit >>= 1;
+            }
+            j |= bit;
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+        let mut len = 2;
+        while len <= n {
+            let stride = n / len;
+            for start in (0..n).step_by(len) {
+                for k in 0..len / 2 {
+                    let (c, s) = self.roots[k * stride];
+                    let (ur, ui) = data[start + k];
+                    let (vr, vi) = data[start + k + len / 2];
+                    let (tr, ti) = (vr * c - vi * s, vr * s + vi * c);
+                    data[start + k] = (ur + tr, ui + ti);
+                    data[start + k + len / 2] = (ur - tr, ui - ti);
+                }
+            }
+            len <<= 1;
+        }
+    }
+}
+
+/// LSB-first bit packer, as Vorbis packets use.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    /// Appends the low `bits` (at most 32) bits of `value`.
+    fn write(&mut self, value: u64, bits: u32) {
+        self.acc |= (value & ((1 << bits) - 1)) << self.len;
+        self.len += bits;
+        while self.len >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lewton::inside_ogg::OggStreamReader;
+    use std::io::Cursor;
+
+    /// Encodes `frames` frames from `sample(frame, channel)`, decodes them
+    /// and returns the input and output per channel.
+    fn roundtrip(
+        channels: u16,
+        frames: usize,
+        sample: impl Fn(usize, usize) -> f32,
+    ) -> Vec<(Vec<f32>, Vec<f32>)> {
+        let tags = [("TITLE", "Round trip".to_string())];
+        let mut writer = VorbisWriter::new(Cursor::new(Vec::new()), 48_000, channels, &tags)
+            .expect("valid format");
+        let mut input = vec![Vec::with_capacity(frames); channels as usize];
+        for frame in 0..frames {
+            for (channel, samples) in input.iter_mut().enumerate() {
+                samples.push(sample(frame, channel));
+                writer.write_sample(samples[frame]).unwrap();
+            }
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let mut reader = OggStreamReader::new(Cursor::new(bytes)).expect("decodable stream");
+        assert_eq!(reader.ident_hdr.audio_channels, channels as u8);
+        assert_eq!(reader.ident_hdr.audio_sample_rate, 48_000);
+        assert_eq!(
+            reader.comment_hdr.comment_list,
+            [("TITLE".to_string(), "Round trip".to_string())]
+        );
+        let mut output = vec![Vec::with_capacity(frames); channels as usize];
+        while let Some(packet) = reader.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap() {
+            for (samples, decoded) in output.iter_mut().zip(packet) {
+                samples.extend(decoded);
+            }
+        }
+        for samples in &output {
+            assert_eq!(samples.len(), frames, "{frames} frames");
+        }
+        input.into_iter().zip(output).collect()
+    }
+
+    fn snr_db(input: &[f32], output: &[f32]) -> f64 {
+        let signal: f64 = input.iter().map(|&x| (x as f64).powi(2)).sum();
+        let noise: f64 = input
+            .iter()
+            .zip(output)
+            .map(|(&x, &y)| (x as f64 - y as f64).powi(2))
+            .sum();
+        10.0 * (signal / noise).log10()
+    }
+
+    #[test]
+    fn decodes_close_to_the_input() {
+        let tone = |frame: usize, hz: f64| (2.0 * PI * hz * frame as f64 / 48_000.0).sin() as f32;
+        let mut seed = 1u32;
+        let noise: Vec<f32> = (0..4096)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect();
+        // Partial last blocks, an exact number of blocks, and a stream
+        // shorter than one block.
+        for frames in [3 * HALF + 123, 4 * HALF, 700] {
+            let channels = roundtrip(2, frames, |i, c| match c {
+                0 => 0.5 * tone(i, 440.0) + 0.2 * tone(i, 3520.0),
+                _ => 0.4 * noise[i % noise.len()],
+            });
+            for (input, output) in &channels {
+                let snr = snr_db(input, output);
+                assert!(snr > 20.0, "{frames} frames: {snr:.1} dB");
+            }
+        }
+
+        // Full-scale squares have the widest residues.
+        let [(input, output)] = roundtrip(
+            1,
+            4 * HALF,
+            |i, _| {
+                if (i / 40) % 2 == 0 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            },
+        )
+        .try_into()
+        .unwrap();
+        assert!(snr_db(&input, &output) > 20.0);
+    }
+
+    #[test]
+    fn silence_stays_silent() {
+        for frames in [0, 5, 3 * HALF] {
+            for (_, output) in roundtrip(2, frames, |_, _| 0.0) {
+                assert!(output.iter().all(|&s| s == 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn codebooks_are_complete_trees() {
+        for book in Setup::new().books {
+            assert!(book.lengths.iter().all(|&l| (1..=32).contains(&l)));
+            let kraft: f64 = book.lengths.iter().map(|&l| 0.5f64.powi(l as i32)).sum();
+            assert!((kraft - 1.0).abs() < 1e-12, "{kraft}");
+        }
+    }
+
+    #[test]
+    fn mdct_matches_the_definition() {
+        let input: Vec<f64> = (0..BLOCK)
+            .map(|n| ((n * 7919) % 1000) as f64 / 500.0 - 1.0)
+            .collect();
+        let fast = Mdct::new().forward(&input);
+        for k in [0, 1, 2, 17, 500, HALF - 2, HALF - 1] {
+            let direct: f64 = input
+                .iter()
+                .enumerate()
+                .map(|(n, x)| {
+                    x * (PI / HALF as f64 * (n as f64 + 0.5 + HALF as f64 / 2.0) * (k as f64 + 0.5))
+                        .cos()
+                })
+                .sum();
+            assert!(
+                (fast[k] - direct).abs() < 1e-6,
+                "bin {k}: {} vs {direct}",
+                fast[k]
+            );
+        }
+    }
+}
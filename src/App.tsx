@@ -1,6 +1,7 @@
 import { useEffect, useState } from "react";
 import {
   Envelope,
+  Keyboard,
   Knob,
   Meter,
   Oscillator,
@@ -12,15 +13,52 @@ import {
   getSynthState,
   generateAutomation,
   isAudioRunning,
+  listParameters,
+  noteOff,
+  noteOn,
   renderSample,
-  setSynthState,
+  setParameter,
   startAudio,
   stopAudio,
+  type Curve,
+  type Keyframe,
+  type NamedCurve,
+  type ParameterInfo,
   type Timeline,
   eventsToTimeline,
 } from "./synth";
 import "./App.css";
 
+// Computer keys for C4 up to C5, laid out like a piano.
+const computerKeys = "awsedftgyhujk";
+
+// Pushes one control to the engine when it changes. Only the changed path is
+// sent, so fields without a control here keep their current values.
+const useSynthParameter = (path: string, value: number | string | boolean) => {
+  useEffect(() => {
+    void setParameter(path, value);
+  }, [path, value]);
+};
+
+// Commands reject with the backend's error string.
+const errorMessage = (error: unknown) =>
+  error instanceof Error ? error.message : String(error);
+
+// The first free time after the last keyframe, so a new keyframe never lands
+// on an existing one.
+const nextKeyframeTime = (keyframes: Keyframe[], durationMs: number) => {
+  const used = new Set(keyframes.map((keyframe) => keyframe.time_ms));
+  const last = Math.max(0, ...keyframes.map((keyframe) => keyframe.time_ms));
+  const preferred = keyframes.length === 0 ? 0 : Math.min(durationMs, last + 500);
+  for (let time = preferred; time <= durationMs; time += 50) {
+    if (!used.has(time)) return time;
+  }
+  for (let time = preferred; time >= 0; time -= 50) {
+    if (!used.has(time)) return time;
+  }
+  return preferred;
+};
+
 function App() {
   const [env, setEnv] = useState({
     attack: 0.02,
@@ -46,6 +84,7 @@ function App() {
   });
   const [isPlaying, setIsPlaying] = useState(false);
   const [audioError, setAudioError] = useState<string | null>(null);
+  const [heldNotes, setHeldNotes] = useState<ReadonlySet<number>>(new Set());
   const [timeline, setTimeline] = useState<Timeline>({
     duration_ms: 3000,
     tracks: [
@@ -67,70 +106,141 @@ function App() {
   );
   const [aiStatus, setAiStatus] = useState<string | null>(null);
   const [aiBusy, setAiBusy] = useState(false);
-  const waveformPath = "oscillator.waveform";
-  const booleanPaths = new Set(["oscillator.sync", "global.mono"]);
-  const parameterOptions = [
-    "filter.cutoff",
-    "filter.resonance",
-    "mixer.master",
-    "oscillator.waveform",
-    "oscillator.tune",
-    "oscillator.level",
-    "oscillator.sync",
-    "global.mono",
-    "global.clip_amount",
-  ];
-  const getDefaultValue = (path: string) => {
-    if (path === waveformPath) return "sine";
-    if (booleanPaths.has(path)) return false;
-    return 0;
+  const [parameters, setParameters] = useState<ParameterInfo[]>([]);
+  const automatable = parameters.filter((parameter) => parameter.automatable);
+  const parameterFor = (path: string) =>
+    parameters.find((parameter) => parameter.path === path);
+  const getDefaultValue = (path: string) => parameterFor(path)?.default ?? 0;
+  // Only continuous parameters can ramp; everything else steps.
+  const canRamp = (path: string) => parameterFor(path)?.type === "number";
+  const getDefaultCurve = (path: string): Curve =>
+    canRamp(path) ? "linear" : "step";
+  const inputClass =
+    "rounded-[var(--ui-radius-1)] border border-white/10 bg-zinc-900/80 px-2 py-1 text-[0.6rem] uppercase tracking-[0.2em] text-zinc-200";
+  // An editor matching the parameter's kind in the registry.
+  const valueInput = (
+    path: string,
+    value: Keyframe["value"],
+    onChange: (value: Keyframe["value"]) => void,
+  ) => {
+    const parameter = parameterFor(path);
+    if (parameter?.type === "choice") {
+      return (
+        <select
+          value={String(value)}
+          onChange={(e) => onChange(e.currentTarget.value)}
+          className={inputClass}
+        >
+          {parameter.options.map((option) => (
+            <option key={option} value={option}>
+              {option}
+            </option>
+          ))}
+        </select>
+      );
+    }
+    if (parameter?.type === "bool") {
+      return (
+        <select
+          value={value ? "true" : "false"}
+          onChange={(e) => onChange(e.currentTarget.value === "true")}
+          className={inputClass}
+        >
+          <option value="true">true</option>
+          <option value="false">false</option>
+        </select>
+      );
+    }
+    const range =
+      parameter?.type === "number" || parameter?.type === "integer"
+        ? parameter
+        : undefined;
+    return (
+      <input
+        type="number"
+        min={range?.min}
+        max={range?.max}
+        step={range?.type === "integer" ? range.step : "any"}
+        value={typeof value === "number" ? value : 0}
+        onChange={(e) => onChange(Number(e.currentTarget.value))}
+        className={`w-24 ${inputClass}`}
+      />
+    );
   };
   const [debugState, setDebugState] = useState<string>("{}");
   const [debugPaused, setDebugPaused] = useState(false);
   const [isFocused, setIsFocused] = useState(true);
   const [pollIntervalMs, setPollIntervalMs] = useState(750);
 
-  useEffect(() => {
-    void setSynthState({
-      envelope: env,
-      oscillator: {
-        waveform: osc.waveform,
-        tune: osc.tune,
-        level: osc.level,
-        sync,
-      },
-      filter: {
-        cutoff,
-        resonance,
-        env_amount: envAmount,
-        drive,
-      },
-      mixer: {
-        noise,
-        sub,
-        master,
-      },
-      global: {
-        mono,
-        glide,
-        clip_amount: clipAmount,
-      },
+  useSynthParameter("envelope.attack", env.attack);
+  useSynthParameter("envelope.decay", env.decay);
+  useSynthParameter("envelope.sustain", env.sustain);
+  useSynthParameter("envelope.release", env.release);
+  useSynthParameter("oscillator.waveform", osc.waveform);
+  useSynthParameter("oscillator.tune", osc.tune);
+  useSynthParameter("oscillator.level", osc.level);
+  useSynthParameter("oscillator.sync", sync);
+  useSynthParameter("filter.cutoff", cutoff);
+  useSynthParameter("filter.resonance", resonance);
+  useSynthParameter("filter.env_amount", envAmount);
+  useSynthParameter("filter.drive", drive);
+  useSynthParameter("mixer.noise", noise);
+  useSynthParameter("mixer.sub", sub);
+  useSynthParameter("mixer.master", master);
+  useSynthParameter("global.mono", mono);
+  useSynthParameter("global.glide", glide);
+  useSynthParameter("global.clip_amount", clipAmount);
+
+  const playNote = (note: number) => {
+    setHeldNotes((prev) => new Set(prev).add(note));
+    noteOn(note, 0.8).catch(() => setAudioError("Note on failed."));
+  };
+  const releaseNote = (note: number) => {
+    setHeldNotes((prev) => {
+      const next = new Set(prev);
+      next.delete(note);
+      return next;
     });
-  }, [
-    env,
-    osc,
-    sync,
-    cutoff,
-    resonance,
-    envAmount,
-    drive,
-    noise,
-    sub,
-    master,
-    mono,
-    glide,
-    clipAmount,
-  ]);
+    noteOff(note).catch(() => setAudioError("Note off failed."));
+  };
+
+  useEffect(() => {
+    if (!isPlaying) return;
+    const noteFor = (event: KeyboardEvent) => {
+      if (event.repeat || event.ctrlKey || event.metaKey || event.altKey) {
+        return null;
+      }
+      if (
+        event.target instanceof HTMLElement &&
+        event.target.closest("input, textarea, select")
+      ) {
+        return null;
+      }
+      const index = computerKeys.indexOf(event.key.toLowerCase());
+      return index < 0 ? null : 60 + index;
+    };
+    const onKeyDown = (event: KeyboardEvent) => {
+      const note = noteFor(event);
+      if (note !== null) playNote(note);
+    };
+    const onKeyUp = (event: KeyboardEvent) => {
+      const index = computerKeys.indexOf(event.key.toLowerCase());
+      if (index >= 0) releaseNote(60 + index);
+    };
+    window.addEventListener("keydown", onKeyDown);
+    window.addEventListener("keyup", onKeyUp);
+    return () => {
+      window.removeEventListener("keydown", onKeyDown);
+      window.removeEventListener("keyup", onKeyUp);
+      setHeldNotes(new Set());
+    };
+  }, [isPlaying]);
+
+  useEffect(() => {
+    listParameters()
+      .then(setParameters)
+      .catch(() => setTimelineStatus("Failed to load parameters."));
+  }, []);
 
   useEffect(() => {
     const onFocus = () => setIsFocused(true);
@@ -370,6 +480,17 @@ function App() {
               </div>
             </div>
           </div>
+          <Keyboard
+            className="mt-8"
+            startNote={48}
+            octaves={3}
+            activeNotes={heldNotes}
+            label="Keys (A-K on the keyboard)"
+            disabled={!isPlaying}
+            tooltipText="Start audio to play"
+            onNoteOn={playNote}
+            onNoteOff={releaseNote}
+          />
         </section>
         <section className="rounded-[var(--ui-radius-2)] border border-white/10 bg-zinc-950/70 p-4">
           <div className="mb-2 flex flex-wrap items-center justify-between gap-3 text-xs uppercase tracking-[0.3em] text-zinc-500">
@@ -463,8 +584,8 @@ function App() {
                     events,
                   });
                   setRenderPath(path);
-                } catch {
-                  setRenderError("Render failed.");
+                } catch (error) {
+                  setRenderError(errorMessage(error));
                 }
               }}
             >
@@ -478,7 +599,18 @@ function App() {
                   ...prev,
                   tracks: [
                     ...prev.tracks,
-                    { path: parameterOptions[0], keyframes: [] },
+                    {
+                      path:
+                        automatable.find(
+                          (parameter) =>
+                            !prev.tracks.some(
+                              (track) => track.path === parameter.path,
+                            ),
+                        )?.path ??
+                        automatable[0]?.path ??
+                        "filter.cutoff",
+                      keyframes: [],
+                    },
                   ],
                 }))
               }
@@ -521,8 +653,8 @@ function App() {
                     });
                     setTimeline(eventsToTimeline(events, timeline.duration_ms));
                     setAiStatus("AI automation loaded.");
-                  } catch {
-                    setAiStatus("AI automation failed.");
+                  } catch (error) {
+                    setAiStatus(`AI automation failed: ${errorMessage(error)}`);
                   } finally {
                     setAiBusy(false);
                   }
@@ -555,6 +687,9 @@ function App() {
                             (keyframe) => ({
                               ...keyframe,
                               value: getDefaultValue(nextPath),
+                              curve: canRamp(nextPath)
+                                ? keyframe.curve
+                                : getDefaultCurve(nextPath),
                             }),
                           );
                           nextTracks[trackIndex] = nextTrack;
@@ -564,9 +699,12 @@ function App() {
                       }}
                       className="rounded-[var(--ui-radius-1)] border border-white/10 bg-zinc-900/80 px-2 py-1 text-[0.6rem] uppercase tracking-[0.2em] text-zinc-200"
                     >
-                      {parameterOptions.map((option) => (
-                        <option key={option} value={option}>
-                          {option}
+                      {parameterFor(track.path) ? null : (
+                        <option value={track.path}>{track.path}</option>
+                      )}
+                      {automatable.map((parameter) => (
+                        <option key={parameter.path} value={parameter.path}>
+                          {parameter.name}
                         </option>
                       ))}
                     </select>
@@ -581,10 +719,14 @@ function App() {
                         const nextTrack = { ...nextTracks[trackIndex] };
                         const nextKeyframes = [...nextTrack.keyframes];
                         nextKeyframes.push({
-                          time_ms: Math.min(prev.duration_ms, 1000),
+                          time_ms: nextKeyframeTime(
+                            nextKeyframes,
+                            prev.duration_ms,
+                          ),
                           value: getDefaultValue(nextTrack.path),
-                          curve: "linear",
+                          curve: getDefaultCurve(nextTrack.path),
                         });
+                        nextKeyframes.sort((a, b) => a.time_ms - b.time_ms);
                         nextTrack.keyframes = nextKeyframes;
                         nextTracks[trackIndex] = nextTrack;
                         next.tracks = nextTracks;
@@ -645,111 +787,38 @@ function App() {
                           className="w-20 rounded-[var(--ui-radius-1)] border border-white/10 bg-zinc-900/80 px-2 py-1 text-[0.6rem] uppercase tracking-[0.2em] text-zinc-200"
                         />
                       </label>
-                      {track.path === waveformPath ? (
-                        <label className="flex items-center gap-2">
-                          Value
-                          <select
-                            value={
-                              typeof keyframe.value === "string"
-                                ? keyframe.value
-                                : "sine"
-                            }
-                            onChange={(e) => {
-                              const nextValue = e.currentTarget.value;
-                              setTimeline((prev) => {
-                                const next = { ...prev };
-                                const nextTracks = [...next.tracks];
-                                const nextTrack = { ...nextTracks[trackIndex] };
-                                const nextKeyframes = [...nextTrack.keyframes];
-                                const nextKeyframe = {
-                                  ...nextKeyframes[keyIndex],
-                                };
-                                nextKeyframe.value = nextValue;
-                                nextKeyframes[keyIndex] = nextKeyframe;
-                                nextTrack.keyframes = nextKeyframes;
-                                nextTracks[trackIndex] = nextTrack;
-                                next.tracks = nextTracks;
-                                return next;
-                              });
-                            }}
-                            className="rounded-[var(--ui-radius-1)] border border-white/10 bg-zinc-900/80 px-2 py-1 text-[0.6rem] uppercase tracking-[0.2em] text-zinc-200"
-                          >
-                            <option value="sine">sine</option>
-                            <option value="triangle">triangle</option>
-                            <option value="saw">saw</option>
-                            <option value="square">square</option>
-                          </select>
-                        </label>
-                      ) : booleanPaths.has(track.path) ? (
-                        <label className="flex items-center gap-2">
-                          Value
-                          <select
-                            value={keyframe.value ? "true" : "false"}
-                            onChange={(e) => {
-                              const nextValue = e.currentTarget.value === "true";
-                              setTimeline((prev) => {
-                                const next = { ...prev };
-                                const nextTracks = [...next.tracks];
-                                const nextTrack = { ...nextTracks[trackIndex] };
-                                const nextKeyframes = [...nextTrack.keyframes];
-                                const nextKeyframe = {
-                                  ...nextKeyframes[keyIndex],
-                                };
-                                nextKeyframe.value = nextValue;
-                                nextKeyframes[keyIndex] = nextKeyframe;
-                                nextTrack.keyframes = nextKeyframes;
-                                nextTracks[trackIndex] = nextTrack;
-                                next.tracks = nextTracks;
-                                return next;
-                              });
-                            }}
-                            className="rounded-[var(--ui-radius-1)] border border-white/10 bg-zinc-900/80 px-2 py-1 text-[0.6rem] uppercase tracking-[0.2em] text-zinc-200"
-                          >
-                            <option value="true">true</option>
-                            <option value="false">false</option>
-                          </select>
-                        </label>
-                      ) : (
-                        <label className="flex items-center gap-2">
-                          Value
-                          <input
-                            type="number"
-                            step={1}
-                            value={
-                              typeof keyframe.value === "number"
-                                ? keyframe.value
-                                : 0
-                            }
-                            onChange={(e) => {
-                              const nextValue = Number(e.currentTarget.value);
-                              setTimeline((prev) => {
-                                const next = { ...prev };
-                                const nextTracks = [...next.tracks];
-                                const nextTrack = { ...nextTracks[trackIndex] };
-                                const nextKeyframes = [...nextTrack.keyframes];
-                                const nextKeyframe = {
-                                  ...nextKeyframes[keyIndex],
-                                };
-                                nextKeyframe.value = nextValue;
-                                nextKeyframes[keyIndex] = nextKeyframe;
-                                nextTrack.keyframes = nextKeyframes;
-                                nextTracks[trackIndex] = nextTrack;
-                                next.tracks = nextTracks;
-                                return next;
-                              });
-                            }}
-                            className="w-24 rounded-[var(--ui-radius-1)] border border-white/10 bg-zinc-900/80 px-2 py-1 text-[0.6rem] uppercase tracking-[0.2em] text-zinc-200"
-                          />
-                        </label>
-                      )}
+                      <label className="flex items-center gap-2">
+                        Value
+                        {valueInput(track.path, keyframe.value, (nextValue) =>
+                          setTimeline((prev) => {
+                            const next = { ...prev };
+                            const nextTracks = [...next.tracks];
+                            const nextTrack = { ...nextTracks[trackIndex] };
+                            const nextKeyframes = [...nextTrack.keyframes];
+                            const nextKeyframe = { ...nextKeyframes[keyIndex] };
+                            nextKeyframe.value = nextValue;
+                            nextKeyframes[keyIndex] = nextKeyframe;
+                            nextTrack.keyframes = nextKeyframes;
+                            nextTracks[trackIndex] = nextTrack;
+                            next.tracks = nextTracks;
+                            return next;
+                          }),
+                        )}
+                      </label>
                       <label className="flex items-center gap-2">
                         Curve
                         <select
-                          value={keyframe.curve ?? "step"}
+                          value={
+                            typeof keyframe.curve === "object"
+                              ? "bezier"
+                              : (keyframe.curve ?? "step")
+                          }
                           onChange={(e) => {
-                            const nextValue = e.currentTarget.value as
-                              | "step"
-                              | "linear";
+                            const selected = e.currentTarget.value;
+                            const nextValue: Curve =
+                              selected === "bezier"
+                                ? { bezier: { tension: 0.5 } }
+                                : (selected as NamedCurve);
                             setTimeline((prev) => {
                               const next = { ...prev };
                               const nextTracks = [...next.tracks];
@@ -767,9 +836,49 @@ function App() {
                           className="w-24 rounded-[var(--ui-radius-1)] border border-white/10 bg-zinc-900/80 px-2 py-1 text-[0.6rem] uppercase tracking-[0.2em] text-zinc-200"
                         >
                           <option value="step">Step</option>
-                          <option value="linear">Linear</option>
+                          {canRamp(track.path) ? (
+                            <>
+                              <option value="linear">Linear</option>
+                              <option value="exponential">Exp</option>
+                              <option value="logarithmic">Log</option>
+                              <option value="s_curve">S-curve</option>
+                              <option value="bezier">Bezier</option>
+                            </>
+                          ) : null}
                         </select>
                       </label>
+                      {typeof keyframe.curve === "object" ? (
+                        <label className="flex items-center gap-2">
+                          Tension
+                          <input
+                            type="number"
+                            min={-1}
+                            max={1}
+                            step={0.1}
+                            value={keyframe.curve.bezier.tension}
+                            onChange={(e) => {
+                              const tension = Math.min(
+                                1,
+                                Math.max(-1, Number(e.currentTarget.value) || 0),
+                              );
+                              setTimeline((prev) => {
+                                const next = { ...prev };
+                                const nextTracks = [...next.tracks];
+                                const nextTrack = { ...nextTracks[trackIndex] };
+                                const nextKeyframes = [...nextTrack.keyframes];
+                                const nextKeyframe = { ...nextKeyframes[keyIndex] };
+                                nextKeyframe.curve = { bezier: { tension } };
+                                nextKeyframes[keyIndex] = nextKeyframe;
+                                nextTrack.keyframes = nextKeyframes;
+                                nextTracks[trackIndex] = nextTrack;
+                                next.tracks = nextTracks;
+                                return next;
+                              });
+                            }}
+                            className="w-16 rounded-[var(--ui-radius-1)] border border-white/10 bg-zinc-900/80 px-2 py-1 text-[0.6rem] uppercase tracking-[0.2em] text-zinc-200"
+                          />
+                        </label>
+                      ) : null}
                       <button
                         type="button"
                         className="rounded-[var(--ui-radius-1)] border border-white/10 bg-zinc-900/80 px-2 py-1 text-[0.55rem] uppercase tracking-[0.2em] text-zinc-300 transition hover:text-red-200"
@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 
 export type EnvelopeState = {
   attack: number;
@@ -14,50 +15,145 @@ export type OscillatorState = {
   sync: boolean;
 };
 
+export type Oscillator2State = {
+  waveform: string;
+  tune: number;
+  level: number;
+};
+
 export type FilterState = {
   cutoff: number;
   resonance: number;
   env_amount: number;
   drive: number;
+  mode?: "lowpass" | "highpass" | "bandpass" | "notch";
+  slope?: 12 | 24;
 };
 
 export type MixerState = {
   noise: number;
   sub: number;
   master: number;
+  noise_color?: "white" | "pink";
+  sub_octave?: 1 | 2;
+  pan?: number;
+  width?: number;
+  pan_spread?: number;
+};
+
+export type UnisonState = {
+  voices: number;
+  detune: number;
+  spread: number;
 };
 
 export type GlobalState = {
   mono: boolean;
   glide: number;
   clip_amount: number;
+  legato?: boolean;
+  polyphony?: number;
+  voice_steal?: "oldest" | "quietest";
+  quality?: "draft" | "standard";
+  smoothing_ms?: number;
 };
 
 export type SynthState = {
   envelope: EnvelopeState;
   oscillator: OscillatorState;
+  oscillator2?: Oscillator2State;
   filter: FilterState;
   mixer: MixerState;
+  unison?: UnisonState;
   global: GlobalState;
 };
 
+export type NamedCurve =
+  | "step"
+  | "hold"
+  | "linear"
+  | "exponential"
+  | "logarithmic"
+  | "s_curve";
+
+export type Curve = NamedCurve | { bezier: { tension: number } };
+
+export type ParameterKind =
+  | { type: "number"; min: number; max: number }
+  | { type: "integer"; min: number; max: number; step: number }
+  | { type: "bool" }
+  | { type: "choice"; options: string[] };
+
+export type ParameterInfo = ParameterKind & {
+  path: string;
+  name: string;
+  unit: string;
+  default: number | string | boolean;
+  automatable: boolean;
+};
+
 export type AutomationEvent = {
   time_ms: number;
   path: string;
   value: number | string | boolean;
-  curve?: "step" | "linear";
+  curve?: Curve;
+};
+
+export type EventIssue = {
+  index: number;
+  path: string;
+  kind:
+    | "unknown_path"
+    | "not_automatable"
+    | "type_mismatch"
+    | "out_of_range"
+    | "unsupported_curve"
+    | "invalid_time"
+    | "beyond_duration"
+    | "duplicate_time";
+  message: string;
 };
 
 export type RenderRequest = {
   duration_ms: number;
   sample_rate: number;
   events: AutomationEvent[];
+  note?: number;
+  velocity?: number;
+  gate_ms?: number;
+  channels?: 1 | 2;
+  output_dir?: string;
+  // Tokens: {preset} {date} {time} {timestamp} {sample_rate} {note}
+  // {duration_ms} {channels}
+  filename_template?: string;
+  preset_name?: string;
+  overwrite?: "unique" | "replace" | "fail";
+  bit_depth?: 16 | 24 | 32;
+  sample_format?: "int" | "float";
+  // Only used for 16-bit output.
+  dither?: "none" | "tpdf" | "shaped";
+  // FLAC takes 16 or 24-bit int samples. Ogg Vorbis ignores bit_depth,
+  // sample_format and dither.
+  format?: "wav" | "flac" | "ogg_vorbis";
+};
+
+// Stored in every rendered WAV; enough to render it again.
+export type RenderMetadata = {
+  version: number;
+  preset_name: string | null;
+  state: SynthState;
+  events: AutomationEvent[];
+  note: number;
+  velocity: number;
+  duration_ms: number;
+  gate_ms: number;
+  sample_rate: number;
 };
 
 export type Keyframe = {
   time_ms: number;
   value: number | string | boolean;
-  curve?: "step" | "linear";
+  curve?: Curve;
 };
 
 export type AutomationTrack = {
@@ -114,16 +210,160 @@ export const setSynthState = (state: SynthState) =>
 
 export const resetSynthState = () => invoke<SynthState>("synth_reset");
 
-export const startAudio = () => invoke<boolean>("audio_start");
+export const listParameters = () =>
+  invoke<ParameterInfo[]>("synth_list_parameters");
+
+export const getParameter = (path: string) =>
+  invoke<number | string | boolean>("synth_get_parameter", { path });
+
+export const setParameter = (path: string, value: number | string | boolean) =>
+  invoke("synth_set_parameter", { path, value });
+
+export type AudioOptions = {
+  host?: string | null;
+  device?: string | null;
+  sample_rate?: number | null;
+  buffer_size?: number | null;
+  channels?: number | null;
+};
+
+export type AudioHost = {
+  id: string;
+  name: string;
+  is_default: boolean;
+};
+
+export type AudioDevice = {
+  id: string;
+  name: string;
+  is_default: boolean;
+  channels: number[];
+  min_sample_rate: number;
+  max_sample_rate: number;
+  default_sample_rate: number | null;
+  default_channels: number | null;
+  min_buffer_size: number | null;
+  max_buffer_size: number | null;
+};
+
+export const listAudioHosts = () => invoke<AudioHost[]>("audio_list_hosts");
+
+export const listAudioDevices = (host?: string) =>
+  invoke<AudioDevice[]>("audio_list_devices", { host });
+
+export const getAudioOptions = () => invoke<AudioOptions>("audio_get_options");
+
+// Without `options`, the options from the last explicit start are reused.
+export const startAudio = (options?: AudioOptions) =>
+  invoke<boolean>("audio_start", { options });
 
 export const stopAudio = () => invoke<boolean>("audio_stop");
 
 export const isAudioRunning = () => invoke<boolean>("audio_is_running");
 
-export const renderSample = (request: RenderRequest) =>
-  invoke<string>("render_sample", { request });
+export const noteOn = (note: number, velocity: number) =>
+  invoke("note_on", { note, velocity });
+
+export const noteOff = (note: number) => invoke("note_off", { note });
+
+export type RenderStarted = {
+  job_id: number;
+  path: string;
+};
+
+export type RenderProgress = {
+  job_id: number;
+  percent: number;
+  elapsed_ms: number;
+  eta_ms: number | null;
+};
+
+export type RenderFinished =
+  | { status: "done"; job_id: number; path: string }
+  | { status: "failed"; job_id: number; error: string }
+  | { status: "cancelled"; job_id: number };
+
+// Returns once the job has started; follow it with onRenderProgress and
+// onRenderFinished.
+export const startRender = (request: RenderRequest) =>
+  invoke<RenderStarted>("render_sample", { request });
+
+export const cancelRender = (jobId: number) =>
+  invoke<boolean>("render_cancel", { jobId });
+
+export const onRenderProgress = (handler: (progress: RenderProgress) => void) =>
+  listen<RenderProgress>("render://progress", (event) =>
+    handler(event.payload),
+  );
+
+export const onRenderFinished = (handler: (finished: RenderFinished) => void) =>
+  listen<RenderFinished>("render://finished", (event) =>
+    handler(event.payload),
+  );
+
+// Starts a render and resolves with its path when it is done. Rejects if the
+// render fails or is cancelled.
+export const renderSample = async (
+  request: RenderRequest,
+  onProgress?: (progress: RenderProgress) => void,
+) => {
+  let jobId: number | null = null;
+  // Events that arrive before the job id is known are replayed below.
+  const early: RenderFinished[] = [];
+  let finish = (finished: RenderFinished) => {
+    early.push(finished);
+  };
+  const stopProgress = await onRenderProgress((progress) => {
+    if (progress.job_id === jobId) onProgress?.(progress);
+  });
+  const stopFinished = await onRenderFinished((finished) => finish(finished));
+  try {
+    const job = await startRender(request);
+    jobId = job.job_id;
+    const finished = await new Promise<RenderFinished>((resolve) => {
+      finish = (finished) => {
+        if (finished.job_id === job.job_id) resolve(finished);
+      };
+      early.forEach(finish);
+    });
+    if (finished.status === "failed") throw new Error(finished.error);
+    if (finished.status === "cancelled") throw new Error("Render cancelled.");
+    return finished.path;
+  } finally {
+    stopProgress();
+    stopFinished();
+  }
+};
+
+export const readRenderMetadata = (path: string) =>
+  invoke<RenderMetadata>("render_read_metadata", { path });
 
 export const generateAutomation = (request: {
   prompt: string;
   duration_ms: number;
 }) => invoke<AutomationEvent[]>("ai_generate_automation", { request });
+
+export const validateAutomation = (
+  events: AutomationEvent[],
+  duration_ms: number,
+) => invoke<EventIssue[]>("automation_validate", { events, durationMs: duration_ms });
+
+export type TransportPosition = {
+  position_ms: number;
+  playing: boolean;
+};
+
+export const playAutomation = (
+  events: AutomationEvent[],
+  loop: boolean,
+  duration_ms?: number,
+) => invoke("automation_play", { events, loop, durationMs: duration_ms });
+
+export const stopAutomation = () => invoke("automation_stop");
+
+export const onTransportPosition = (
+  handler: (position: TransportPosition) => void,
+) =>
+  listen<TransportPosition>("automation://position", (event) =>
+    handler(event.payload),
+  );
@@ -0,0 +1,100 @@
+import { useRef } from "react";
+import type { PointerEvent } from "react";
+import { DisabledTooltip } from "./DisabledTooltip";
+import { Label } from "./Label";
+import { disabledSurfaceClass } from "./utils";
+
+type KeyboardProps = {
+  startNote?: number;
+  octaves?: number;
+  activeNotes?: ReadonlySet<number>;
+  label?: string;
+  disabled?: boolean;
+  tooltipText?: string;
+  onNoteOn?: (note: number) => void;
+  onNoteOff?: (note: number) => void;
+  className?: string;
+};
+
+const blackKeys = new Set([1, 3, 6, 8, 10]);
+
+export function Keyboard({
+  startNote = 48,
+  octaves = 2,
+  activeNotes,
+  label,
+  disabled = false,
+  tooltipText = "Disabled",
+  onNoteOn,
+  onNoteOff,
+  className = "",
+}: KeyboardProps) {
+  // Notes held by each pointer, so sliding off a key releases it.
+  const held = useRef(new Map<number, number>());
+  const notes = Array.from({ length: octaves * 12 + 1 }, (_, i) => startNote + i);
+  const whiteNotes = notes.filter((note) => !blackKeys.has(note % 12));
+  const whiteWidth = 100 / whiteNotes.length;
+
+  const press = (note: number) => (event: PointerEvent<HTMLButtonElement>) => {
+    if (disabled || held.current.has(event.pointerId)) return;
+    held.current.set(event.pointerId, note);
+    onNoteOn?.(note);
+  };
+  const release = (event: PointerEvent<HTMLButtonElement>) => {
+    const note = held.current.get(event.pointerId);
+    if (note === undefined) return;
+    held.current.delete(event.pointerId);
+    onNoteOff?.(note);
+  };
+  const keyProps = (note: number) => ({
+    type: "button" as const,
+    "aria-label": `Note ${note}`,
+    "aria-pressed": activeNotes?.has(note) ?? false,
+    disabled,
+    onPointerDown: press(note),
+    onPointerUp: release,
+    onPointerLeave: release,
+    onPointerCancel: release,
+  });
+
+  return (
+    <div className={`group relative select-none ${className}`}>
+      <div
+        className={`relative h-[var(--ui-size-3)] touch-none ${disabledSurfaceClass(disabled)}`}
+      >
+        {whiteNotes.map((note, index) => (
+          <button
+            key={note}
+            {...keyProps(note)}
+            className={`absolute inset-y-0 rounded-b-[var(--ui-radius-1)] border border-zinc-900/80 transition ${
+              activeNotes?.has(note) ? "bg-amber-200" : "bg-zinc-200"
+            }`}
+            style={{ left: `${index * whiteWidth}%`, width: `${whiteWidth}%` }}
+          />
+        ))}
+        {notes
+          .filter((note) => blackKeys.has(note % 12))
+          .map((note) => {
+            const whitesBefore = whiteNotes.filter((white) => white < note).length;
+            return (
+              <button
+                key={note}
+                {...keyProps(note)}
+                className={`absolute top-0 z-10 h-3/5 rounded-b-[var(--ui-radius-1)] border border-zinc-900 transition ${
+                  activeNotes?.has(note) ? "bg-amber-300" : "bg-zinc-800"
+                }`}
+                style={{
+                  left: `${(whitesBefore - 0.3) * whiteWidth}%`,
+                  width: `${whiteWidth * 0.6}%`,
+                }}
+              />
+            );
+          })}
+      </div>
+      {disabled ? <DisabledTooltip text={tooltipText} /> : null}
+      {label ? (
+        <Label text={label} disabled={disabled} className="mt-[var(--ui-space-3)]" />
+      ) : null}
+    </div>
+  );
+}
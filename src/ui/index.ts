@@ -10,3 +10,4 @@ export { Envelope } from "./Envelope";
 export { Oscillator } from "./Oscillator";
 export { WaveformSelect } from "./WaveformSelect";
 export { Meter } from "./Meter";
+export { Keyboard } from "./Keyboard";